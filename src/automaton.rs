@@ -0,0 +1,322 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cellular automata, where each cell's next value depends only on itself
+//! and its neighbors.
+//!
+//! An `Automaton` runs over a `Matrix`, and a `Tape` over an unbounded row of
+//! cells. Both keep a second buffer that's reused every generation, so
+//! stepping doesn't allocate once the buffers are big enough.
+
+use std::iter;
+use std::ops::Range;
+use std::thread;
+
+use crate::{point, Matrix};
+
+/// A 2D automaton where each cell sees its 8-way neighbors.
+#[derive(Clone, Debug)]
+pub struct Automaton<T> {
+    cur: Matrix<T>,
+    /// Where the next generation is built; its contents are meaningless
+    /// between steps.
+    next: Matrix<T>,
+    generation: usize,
+}
+
+impl<T: Clone> Automaton<T> {
+    pub fn new(m: Matrix<T>) -> Automaton<T> {
+        Automaton {
+            next: m.clone(),
+            cur: m,
+            generation: 0,
+        }
+    }
+
+    /// The current state.
+    pub fn matrix(&self) -> &Matrix<T> {
+        &self.cur
+    }
+
+    pub fn into_matrix(self) -> Matrix<T> {
+        self.cur
+    }
+
+    /// The number of steps taken.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Advance one generation.
+    ///
+    /// `rule` gets each cell and the values of its neighbors within the
+    /// matrix, in reading order, and returns the cell's new value.
+    pub fn step(&mut self, rule: impl Fn(&T, &[T]) -> T) {
+        let mut ns = Vec::with_capacity(8);
+        for (y, row) in self.next.rows_mut().enumerate() {
+            step_row(&self.cur, y, row, &mut ns, &rule);
+        }
+        self.finish_step();
+    }
+
+    /// Advance one generation, like `step`, but split the rows between up
+    /// to `threads` threads.
+    pub fn step_parallel(&mut self, threads: usize, rule: impl Fn(&T, &[T]) -> T + Sync)
+    where
+        T: Send + Sync,
+    {
+        let rows_per_thread = self.cur.height().div_ceil(threads.max(1)).max(1);
+        let cur = &self.cur;
+        let rule = &rule;
+        let mut rows: Vec<(usize, &mut [T])> = self.next.rows_mut().enumerate().collect();
+        thread::scope(|s| {
+            for chunk in rows.chunks_mut(rows_per_thread) {
+                s.spawn(move || {
+                    let mut ns = Vec::with_capacity(8);
+                    for (y, row) in chunk.iter_mut() {
+                        step_row(cur, *y, row, &mut ns, rule);
+                    }
+                });
+            }
+        });
+        self.finish_step();
+    }
+
+    fn finish_step(&mut self) {
+        std::mem::swap(&mut self.cur, &mut self.next);
+        self.generation += 1;
+    }
+}
+
+/// Automata are equal if their current states are, whatever generation
+/// they're at.
+impl<T: PartialEq> PartialEq for Automaton<T> {
+    fn eq(&self, other: &Automaton<T>) -> bool {
+        self.cur == other.cur
+    }
+}
+
+impl<T: Eq> Eq for Automaton<T> {}
+
+/// Compute the new values for row `y` into `row`, using `ns` as scratch
+/// space for the neighbors.
+fn step_row<T: Clone>(
+    cur: &Matrix<T>,
+    y: usize,
+    row: &mut [T],
+    ns: &mut Vec<T>,
+    rule: &impl Fn(&T, &[T]) -> T,
+) {
+    for (x, out) in row.iter_mut().enumerate() {
+        ns.clear();
+        for ny in y.saturating_sub(1)..=(y + 1) {
+            for nx in x.saturating_sub(1)..=(x + 1) {
+                if (nx, ny) != (x, y) {
+                    if let Some(v) = cur.get(point(nx, ny)) {
+                        ns.push(v.clone());
+                    }
+                }
+            }
+        }
+        *out = rule(&cur[point(x, y)], ns);
+    }
+}
+
+/// A 1D automaton over cells numbered by any integer, where each cell sees
+/// the `radius` cells on either side.
+///
+/// Everything outside the stored range has the default value, and the rule
+/// must map a window of all defaults back to the default.
+#[derive(Clone, Debug)]
+pub struct Tape<T> {
+    /// Number of the first stored cell.
+    start: isize,
+    /// Stored cells, which never start or end with the default.
+    cells: Vec<T>,
+    /// The cells padded with defaults, built during each step.
+    padded: Vec<T>,
+    default: T,
+    radius: usize,
+    generation: usize,
+}
+
+impl<T: Clone + PartialEq> Tape<T> {
+    /// Make a tape with `cells` numbered from 0.
+    pub fn new(cells: Vec<T>, default: T, radius: usize) -> Tape<T> {
+        let mut t = Tape {
+            start: 0,
+            cells,
+            padded: Vec::new(),
+            default,
+            radius,
+            generation: 0,
+        };
+        t.trim();
+        t
+    }
+
+    /// The value of cell `i`.
+    pub fn get(&self, i: isize) -> &T {
+        let j = i - self.start;
+        if j >= 0 && (j as usize) < self.cells.len() {
+            &self.cells[j as usize]
+        } else {
+            &self.default
+        }
+    }
+
+    /// The numbers of the stored cells: the first and last are never the
+    /// default.
+    pub fn range(&self) -> Range<isize> {
+        self.start..(self.start + self.cells.len() as isize)
+    }
+
+    /// The stored cells, starting from `range().start`.
+    pub fn cells(&self) -> &[T] {
+        &self.cells
+    }
+
+    /// The stored cells and their numbers.
+    pub fn iter(&self) -> impl Iterator<Item = (isize, &T)> {
+        self.range().zip(self.cells.iter())
+    }
+
+    /// The number of steps taken.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Advance one generation.
+    ///
+    /// `rule` gets the `2 * radius + 1` cells centered on each cell, and
+    /// returns that cell's new value.
+    pub fn step(&mut self, rule: impl Fn(&[T]) -> T) {
+        // Anything within `radius` of a stored cell might change, and to
+        // compute those we need another `radius` of defaults beyond them.
+        let pad = 2 * self.radius;
+        self.padded.clear();
+        self.padded
+            .extend(iter::repeat_n(self.default.clone(), pad));
+        self.padded.extend_from_slice(&self.cells);
+        self.padded
+            .extend(iter::repeat_n(self.default.clone(), pad));
+        self.cells.clear();
+        self.cells.extend(self.padded.windows(pad + 1).map(&rule));
+        self.start -= self.radius as isize;
+        self.generation += 1;
+        self.trim();
+    }
+
+    /// Drop defaults from both ends.
+    fn trim(&mut self) {
+        let default = &self.default;
+        let lead = self.cells.iter().take_while(|c| *c == default).count();
+        if lead == self.cells.len() {
+            self.cells.clear();
+            return;
+        }
+        let trail = self
+            .cells
+            .iter()
+            .rev()
+            .take_while(|c| *c == default)
+            .count();
+        self.cells.truncate(self.cells.len() - trail);
+        self.cells.drain(..lead);
+        self.start += lead as isize;
+    }
+}
+
+/// Tapes are equal if their current states are, whatever generation they're
+/// at.
+impl<T: PartialEq> PartialEq for Tape<T> {
+    fn eq(&self, other: &Tape<T>) -> bool {
+        self.start == other.start && self.cells == other.cells && self.default == other.default
+    }
+}
+
+impl<T: Eq> Eq for Tape<T> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn life(alive: &bool, ns: &[bool]) -> bool {
+        let n = ns.iter().filter(|n| **n).count();
+        n == 3 || (*alive && n == 2)
+    }
+
+    fn glider() -> Matrix<bool> {
+        Matrix::<char>::parse_ascii(
+            "\
+             .#......\n\
+             ..#.....\n\
+             ###.....\n\
+             ........\n\
+             ........\n\
+             ........\n",
+        )
+        .unwrap()
+        .map(|c| *c == '#')
+    }
+
+    #[test]
+    fn glider_moves() {
+        let mut a = Automaton::new(glider());
+        for _ in 0..4 {
+            a.step(life);
+        }
+        assert_eq!(a.generation(), 4);
+        // After four generations, a glider has moved one square diagonally.
+        let moved = Matrix::from_fn(8, 6, |p| {
+            p.x > 0 && p.y > 0 && glider()[point(p.x - 1, p.y - 1)]
+        });
+        assert_eq!(a.matrix(), &moved);
+    }
+
+    #[test]
+    fn parallel_matches_serial() {
+        let mut a = Automaton::new(glider());
+        let mut b = a.clone();
+        for threads in &[1, 2, 3, 4, 100] {
+            a.step(life);
+            b.step_parallel(*threads, life);
+            assert_eq!(a.matrix(), b.matrix());
+        }
+        assert_eq!(b.generation(), 5);
+    }
+
+    #[test]
+    fn tape_grows_and_trims() {
+        // Rule 90: each cell becomes the xor of its two neighbors.
+        let mut t = Tape::new(vec![0u8, 0, 1, 0], 0, 1);
+        assert_eq!(t.range(), 2..3);
+        let render = |t: &Tape<u8>| -> String {
+            (-4..9)
+                .map(|i| if *t.get(i) == 1 { '#' } else { '.' })
+                .collect()
+        };
+        assert_eq!(render(&t), "......#......");
+        t.step(|w| w[0] ^ w[2]);
+        assert_eq!(render(&t), ".....#.#.....");
+        t.step(|w| w[0] ^ w[2]);
+        assert_eq!(render(&t), "....#...#....");
+        assert_eq!(t.range(), 0..5);
+        assert_eq!(t.cells(), &[1, 0, 0, 0, 1]);
+        // A rule that kills everything leaves the tape empty.
+        t.step(|_| 0);
+        assert_eq!(t.range().len(), 0);
+        assert_eq!(t.generation(), 3);
+    }
+}
@@ -95,6 +95,7 @@ mod tests {
         );
     }
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_onediff() {
         use super::onediff;
 
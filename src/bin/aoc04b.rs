@@ -40,10 +40,10 @@ pub fn main() {
     let mut best_i: Option<usize> = None;
     for n in ns {
         let sg = min_by_guard.entry(n.guard).or_insert([0u32; 60]);
-        for i in n.sleep_min..n.wake_min {
-            sg[i] += 1;
-            if sg[i] > best_sleeps {
-                best_sleeps = sg[i];
+        for (i, sgi) in sg.iter_mut().enumerate().take(n.wake_min).skip(n.sleep_min) {
+            *sgi += 1;
+            if *sgi > best_sleeps {
+                best_sleeps = *sgi;
                 best_i = Some(i);
                 best_guard = Some(n.guard);
             }
@@ -30,7 +30,7 @@ pub fn main() {
 }
 
 fn matches(c0: char, c1: char) -> bool {
-    c0.to_ascii_lowercase() == c1.to_ascii_lowercase()
+    c0.eq_ignore_ascii_case(&c1)
         && c0.is_ascii_uppercase() != c1.is_ascii_uppercase()
 }
 
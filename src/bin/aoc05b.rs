@@ -25,10 +25,10 @@ pub fn main() {
         .collect::<Vec<_>>();
     assert_eq!(lines.len(), 1);
     let l = &lines[0];
-    let mut best_len = usize::max_value();
+    let mut best_len = usize::MAX;
     let mut best_char = 0u8;
     for t in b'a'..=b'z' {
-        let c = collapse_without(&l, t as char);
+        let c = collapse_without(l, t as char);
         println!("Remove {:?} => len {}", t as char, c.len());
         if c.len() < best_len {
             best_len = c.len();
@@ -42,7 +42,7 @@ pub fn main() {
 }
 
 fn matches(c0: char, c1: char) -> bool {
-    c0.to_ascii_lowercase() == c1.to_ascii_lowercase()
+    c0.eq_ignore_ascii_case(&c1)
         && c0.is_ascii_uppercase() != c1.is_ascii_uppercase()
 }
 
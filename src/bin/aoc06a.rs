@@ -44,22 +44,19 @@ use std::fmt;
 use std::io;
 use std::io::prelude::*;
 
+use aoc2018::{ipoint, Grid, IPoint};
+
 pub fn main() {
-    let pts: Vec<Point> = io::stdin()
+    let pts: Vec<IPoint> = io::stdin()
         .lock()
         .lines()
         .map(Result::unwrap)
-        .map(|s| Point::from_string(&s))
+        .map(|s| parse_point(&s))
         .collect();
     let m = Map::from_points(&pts).grow_completely();
     println!("largest: {}", m.largest());
 }
 
-type Coord = i32;
-struct Point {
-    x: Coord,
-    y: Coord,
-}
 type Landing = u32;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -73,119 +70,81 @@ enum Color {
 struct Map {
     // For simplicity addressing is zero-based even though that may leave
     // some empty space to the top-left.
-    w: Coord,
-    h: Coord,
-    d: Vec<Color>,
+    w: isize,
+    h: isize,
+    d: Grid<Color>,
 }
 
-impl Point {
-    pub fn from_string(s: &str) -> Point {
-        let mut splits = s.split(", ");
-        Point {
-            x: splits.next().unwrap().parse().unwrap(),
-            y: splits.next().unwrap().parse().unwrap(),
-        }
-    }
-
-    fn up(&self) -> Point {
-        Point {
-            x: self.x,
-            y: self.y - 1,
-        }
-    }
-
-    fn down(&self) -> Point {
-        Point {
-            x: self.x,
-            y: self.y + 1,
-        }
-    }
-
-    fn left(&self) -> Point {
-        Point {
-            x: self.x - 1,
-            y: self.y,
-        }
-    }
-
-    fn right(&self) -> Point {
-        Point {
-            x: self.x + 1,
-            y: self.y,
-        }
-    }
+fn parse_point(s: &str) -> IPoint {
+    let mut splits = s.split(", ");
+    ipoint(
+        splits.next().unwrap().parse().unwrap(),
+        splits.next().unwrap().parse().unwrap(),
+    )
 }
 
 impl Map {
-    pub fn new(w: Coord, h: Coord) -> Map {
+    pub fn new(w: isize, h: isize) -> Map {
         Map {
             w,
             h,
-            d: vec![Color::Unknown; w as usize * h as usize],
+            d: Grid::new(Color::Unknown),
         }
     }
 
     /// Make a new map that will fit all these points
-    pub fn from_points(points: &[Point]) -> Map {
+    pub fn from_points(points: &[IPoint]) -> Map {
         let mut m = Map::new(
             points.iter().map(|p| p.x).max().unwrap() + 2,
             points.iter().map(|p| p.y).max().unwrap() + 2,
         );
         for (i, p) in points.iter().enumerate() {
-            m.set(p, Color::One(i as Landing));
+            m.set(*p, Color::One(i as Landing));
         }
         m
     }
 
-    pub fn set(&mut self, p: &Point, c: Color) {
-        let i = self.idx(p);
+    pub fn set(&mut self, p: IPoint, c: Color) {
+        assert!(self.contains(p), "{:?} is off the map", p);
         assert_eq!(self.get(p), Color::Unknown);
-        self.d[i] = c
+        self.d[p] = c
     }
 
-    pub fn get(&self, p: &Point) -> Color {
-        self.d[self.idx(p)]
+    /// The color at `p`, or Unknown if it's off the map.
+    pub fn get(&self, p: IPoint) -> Color {
+        self.d[p]
     }
 
-    pub fn get_or_unknown(&self, p: &Point) -> Color {
-        if p.x < 0 || p.x >= self.w || p.y < 0 || p.y >= self.h {
-            Color::Unknown
-        } else {
-            self.get(p)
-        }
+    fn contains(&self, p: IPoint) -> bool {
+        (0..self.w).contains(&p.x) && (0..self.h).contains(&p.y)
     }
 
-    fn idx(&self, p: &Point) -> usize {
-        assert!(p.x < self.w);
-        assert!(p.x >= 0);
-        assert!(p.y < self.h);
-        assert!(p.y >= 0);
-        (p.y * self.w + p.x) as usize
+    /// All the points on the map, in reading order.
+    fn points(&self) -> impl Iterator<Item = IPoint> {
+        let w = self.w;
+        (0..self.h).flat_map(move |y| (0..w).map(move |x| ipoint(x, y)))
     }
 
     /// Fill in all squares directly neighboring a new square, returning a
     /// new updated map.
     fn grow(&self) -> Map {
         let mut new = Map::new(self.w, self.h);
-        for y in 0..self.h {
-            for x in 0..self.w {
-                let p = Point { x, y };
-                new.set(
-                    &p,
-                    match self.get(&p) {
-                        Color::Unknown => self.grow_one(&p),
-                        c => c,
-                    },
-                );
-            }
+        for p in self.points() {
+            new.set(
+                p,
+                match self.get(p) {
+                    Color::Unknown => self.grow_one(p),
+                    c => c,
+                },
+            );
         }
         new
     }
 
     /// Get the new color for one currently-unknown square
-    fn grow_one(&self, p: &Point) -> Color {
+    fn grow_one(&self, p: IPoint) -> Color {
         let n = self.neighbors(p);
-        if n.iter().any(|&c| c == Color::Many) {
+        if n.contains(&Color::Many) {
             // If there's any neighbor that's equidistant from multiple landings,
             // then this is too.
             Color::Many
@@ -217,13 +176,8 @@ impl Map {
     }
 
     // Collect all neighbors, or Unknown if they're off the map
-    fn neighbors(&self, p: &Point) -> [Color; 4] {
-        [
-            self.get_or_unknown(&p.up()),
-            self.get_or_unknown(&p.down()),
-            self.get_or_unknown(&p.left()),
-            self.get_or_unknown(&p.right()),
-        ]
+    fn neighbors(&self, p: IPoint) -> [Color; 4] {
+        p.neighbors().map(|n| self.get(n))
     }
 
     /// Return landings that are on the border of the map and can continue
@@ -231,18 +185,18 @@ impl Map {
     pub fn escapees(&self) -> HashSet<Landing> {
         let mut e = HashSet::new();
         // TODO: Is it ever possible to escape through a Many? I think not.
-        fn g(m: &Map, e: &mut HashSet<Landing>, p: &Point) {
+        fn g(m: &Map, e: &mut HashSet<Landing>, p: IPoint) {
             if let Color::One(l) = m.get(p) {
                 e.insert(l);
             }
         }
         for x in 0..self.w {
-            g(self, &mut e, &Point { x, y: 0 });
-            g(self, &mut e, &Point { x, y: self.h - 1 });
+            g(self, &mut e, ipoint(x, 0));
+            g(self, &mut e, ipoint(x, self.h - 1));
         }
         for y in 0..self.h {
-            g(self, &mut e, &Point { x: 0, y });
-            g(self, &mut e, &Point { x: self.w - 1, y });
+            g(self, &mut e, ipoint(0, y));
+            g(self, &mut e, ipoint(self.w - 1, y));
         }
         e
     }
@@ -252,15 +206,13 @@ impl Map {
         let esc = self.escapees();
         let mut cs = HashMap::<Landing, usize>::new();
         let mut best_count: usize = 0;
-        for y in 0..self.h {
-            for x in 0..self.w {
-                if let Color::One(l) = self.get(&Point { x, y }) {
-                    if !esc.contains(&l) {
-                        let e = cs.entry(l).or_insert(0);
-                        *e += 1;
-                        if *e > best_count {
-                            best_count = *e;
-                        }
+        for p in self.points() {
+            if let Color::One(l) = self.get(p) {
+                if !esc.contains(&l) {
+                    let e = cs.entry(l).or_insert(0);
+                    *e += 1;
+                    if *e > best_count {
+                        best_count = *e;
                     }
                 }
             }
@@ -276,7 +228,7 @@ impl fmt::Debug for Map {
                 write!(
                     f,
                     "{}",
-                    match self.get(&Point { x, y }) {
+                    match self.get(ipoint(x, y)) {
                         Color::Unknown => '?',
                         Color::Many => '.',
                         Color::One(c) => (b'A' + c as u8) as char,
@@ -298,7 +250,7 @@ mod test {
     fn simple() {
         let pts: Vec<_> = [(1, 1), (1, 6), (8, 3), (3, 4), (5, 5), (8, 9)]
             .iter()
-            .map(|(x, y)| Point { x: *x, y: *y })
+            .map(|(x, y)| ipoint(*x, *y))
             .collect();
         let m = Map::from_points(&pts);
         println!("{:?}", &m);
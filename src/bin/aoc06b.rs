@@ -21,51 +21,44 @@
 use std::io;
 use std::io::prelude::*;
 
+use aoc2018::{ipoint, IPoint};
+
 pub fn main() {
-    let pts: Vec<Point> = io::stdin()
+    let pts: Vec<IPoint> = io::stdin()
         .lock()
         .lines()
         .map(Result::unwrap)
-        .map(|s| Point::from_string(&s))
+        .map(|s| parse_point(&s))
         .collect();
     let m = Map::from_points(pts);
-    const N: i32 = 10_000;
+    const N: isize = 10_000;
     println!("largest within {}: {}", N, m.count_within_distance(N));
 }
 
-type Coord = i32;
-#[derive(Copy, Clone, Debug, PartialEq)]
-struct Point {
-    x: Coord,
-    y: Coord,
-}
-
 #[derive(Clone, Debug, PartialEq)]
 struct Map {
     // For simplicity addressing is zero-based even though that may leave
     // some empty space to the top-left.
-    w: Coord,
-    h: Coord,
-    ls: Vec<Point>,
+    w: isize,
+    h: isize,
+    ls: Vec<IPoint>,
 }
 
-impl Point {
-    pub fn from_string(s: &str) -> Point {
-        let mut splits = s.split(", ");
-        Point {
-            x: splits.next().unwrap().parse().unwrap(),
-            y: splits.next().unwrap().parse().unwrap(),
-        }
-    }
+fn parse_point(s: &str) -> IPoint {
+    let mut splits = s.split(", ");
+    ipoint(
+        splits.next().unwrap().parse().unwrap(),
+        splits.next().unwrap().parse().unwrap(),
+    )
+}
 
-    fn abs_difference(&self, other: &Point) -> Coord {
-        (self.x - other.x).abs() + (self.y - other.y).abs()
-    }
+fn abs_difference(a: IPoint, b: IPoint) -> isize {
+    (a.x - b.x).abs() + (a.y - b.y).abs()
 }
 
 impl Map {
     /// Make a new map that will fit all these points
-    pub fn from_points(points: Vec<Point>) -> Map {
+    pub fn from_points(points: Vec<IPoint>) -> Map {
         Map {
             w: points.iter().map(|p| p.x).max().unwrap() + 2,
             h: points.iter().map(|p| p.y).max().unwrap() + 2,
@@ -73,14 +66,14 @@ impl Map {
         }
     }
 
-    fn count_within_distance(&self, limit: i32) -> u32 {
+    fn count_within_distance(&self, limit: isize) -> u32 {
         let mut n = 0;
         for y in 0..self.h {
             for x in 0..self.w {
                 let mut t = 0;
-                let p = Point { x, y };
+                let p = ipoint(x, y);
                 for l in &self.ls {
-                    t += p.abs_difference(l);
+                    t += abs_difference(p, *l);
                     if t > limit {
                         break;
                     }
@@ -102,7 +95,7 @@ mod test {
     fn simple() {
         let pts: Vec<_> = [(1, 1), (1, 6), (8, 3), (3, 4), (5, 5), (8, 9)]
             .iter()
-            .map(|(x, y)| Point { x: *x, y: *y })
+            .map(|(x, y)| ipoint(*x, *y))
             .collect();
         let m = Map::from_points(pts);
         println!("{:?}", &m);
@@ -71,16 +71,11 @@ impl Constraints {
 
     /// Return the next step available for anyone to do.
     pub fn next_step(&mut self) -> Option<Step> {
-        if let Some(s) = self
+        self
             .deps
             .iter()
             .filter_map(|(k, v)| if v.is_empty() { Some(k) } else { None })
-            .next()
-        {
-            Some(*s)
-        } else {
-            None
-        }
+            .next().copied()
     }
 
     /// Mark this step as completed; its dependencies are now available.
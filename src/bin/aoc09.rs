@@ -53,7 +53,7 @@ struct Circle {
     player: usize,
 }
 
-const UNLINKED: (usize, usize) = (usize::max_value(), usize::max_value());
+const UNLINKED: (usize, usize) = (usize::MAX, usize::MAX);
 
 impl Circle {
     pub fn new(n_players: usize) -> Circle {
@@ -75,7 +75,7 @@ impl Circle {
     }
 
     pub fn pred(&self, i: usize) -> usize {
-        assert_ne!(i, usize::max_value());
+        assert_ne!(i, usize::MAX);
         assert_eq!(self.links[self.links[i].0].1, i);
         self.links[i].0
     }
@@ -85,9 +85,9 @@ impl Circle {
         let i = self.succ(self.current);
         let n = self.next_marble();
         self.links.push((i, self.succ(i)));
-        let follow = self.succ(i) as usize;
+        let follow = self.succ(i);
         self.links[follow].0 = n;
-        self.links[i as usize].1 = n;
+        self.links[i].1 = n;
         self.current = n;
         n
     }
@@ -110,7 +110,7 @@ impl Circle {
     /// Insert the next marble or do the special thing for modulo 23.
     pub fn step(&mut self) {
         let n = self.next_marble();
-        if n % 23 == 0 {
+        if n.is_multiple_of(23) {
             self.scores[self.player] += n + self.remove_7back();
             // Mark this marble as played, but not in the circle
             self.links.push(UNLINKED);
@@ -148,7 +148,7 @@ impl Debug for Circle {
         let top = self.top();
         let mut i = top;
         loop {
-            let ll = self.links[i as usize];
+            let ll = self.links[i];
             assert!(ll != UNLINKED);
             if i == self.current {
                 write!(f, "{:>4}", format!("({})", i))?;
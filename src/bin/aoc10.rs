@@ -19,6 +19,8 @@ use std::io::prelude::*;
 use lazy_static::lazy_static;
 use regex::Regex;
 
+use aoc2018::{ipoint, Grid, IPoint};
+
 pub fn main() {
     let mut s = String::new();
     io::stdin().read_to_string(&mut s).unwrap();
@@ -35,11 +37,11 @@ pub fn main() {
 
 #[derive(Debug)]
 pub struct Star {
-    pos: (i32, i32),
-    vel: (i32, i32),
+    pos: IPoint,
+    vel: IPoint,
 }
 
-fn ci(caps: &regex::Captures, i: usize) -> i32 {
+fn ci(caps: &regex::Captures, i: usize) -> isize {
     caps.get(i).unwrap().as_str().parse().unwrap()
 }
 
@@ -62,8 +64,8 @@ impl Map {
             if l.is_empty() {
             } else if let Some(caps) = STAR_RE.captures(l) {
                 ss.push(Star {
-                    pos: (ci(&caps, 1), ci(&caps, 2)),
-                    vel: (ci(&caps, 3), ci(&caps, 4)),
+                    pos: ipoint(ci(&caps, 1), ci(&caps, 2)),
+                    vel: ipoint(ci(&caps, 3), ci(&caps, 4)),
                 });
             } else {
                 panic!("Can't parse {:?}", l);
@@ -75,44 +77,29 @@ impl Map {
     // Returns true if it's feasible to draw.
     pub fn draw(&self) -> bool {
         let ss = &self.ss;
-        let x_min = ss.iter().map(|s| s.pos.0).min().unwrap();
-        let x_max = ss.iter().map(|s| s.pos.0).max().unwrap();
+        let x_min = ss.iter().map(|s| s.pos.x).min().unwrap();
+        let x_max = ss.iter().map(|s| s.pos.x).max().unwrap();
         let w = x_max - x_min;
-        let y_min = ss.iter().map(|s| s.pos.1).min().unwrap();
-        let y_max = ss.iter().map(|s| s.pos.1).max().unwrap();
+        let y_min = ss.iter().map(|s| s.pos.y).min().unwrap();
+        let y_max = ss.iter().map(|s| s.pos.y).max().unwrap();
         let h = y_max - y_min;
-        // println!(
-        //     "x={}..={} ({}), y={}..={} ({}), n={}",
-        //     x_min,
-        //     x_max,
-        //     w,
-        //     y_min,
-        //     y_max,
-        //     h,
-        //     ss.len()
-        // );
+        // Check the size before building a grid, which would allocate the
+        // whole area.
         if w > 80 || h > 60 {
-            // println!("too big!");
             return false;
         }
-        println!("step {}", self.steps);
-        for y in y_min..=y_max {
-            for x in x_min..=x_max {
-                print!("{}", if self.lit((x, y)) { '#' } else { '.' });
-            }
-            println!();
+        let mut sky = Grid::new('.');
+        for s in ss {
+            sky[s.pos] = '#';
         }
+        println!("step {}", self.steps);
+        print!("{}", sky.to_matrix().render_ascii());
         true
     }
 
-    fn lit(&self, pos: (i32, i32)) -> bool {
-        self.ss.iter().any(|s| s.pos == pos)
-    }
-
     pub fn step(&mut self) {
         for s in self.ss.iter_mut() {
-            s.pos.0 += s.vel.0;
-            s.pos.1 += s.vel.1;
+            s.pos = ipoint(s.pos.x + s.vel.x, s.pos.y + s.vel.y);
         }
         self.steps += 1;
     }
@@ -156,7 +143,7 @@ position=<-3,  6> velocity=< 2, -1>";
 
     #[test]
     fn hi() {
-        let mut map = Map::parse(&HI_DEF);
+        let mut map = Map::parse(HI_DEF);
         map.draw();
 
         for _i in 0..3 {
@@ -15,17 +15,7 @@
 //! https://adventofcode.com/2018/day/11
 
 use aoc2018::point;
-use aoc2018::Matrix;
-
-// Performance can probably be improved by remembering the sum of some
-// (aligned? even sized?) blocks and using them when computing the sum of
-// larger enclosing blocks. But the brute force approach works in a basically
-// acceptable amount of time; about 50s.
-//
-// Or: roll lines of squares into and out of the currently computed square,
-// as it moves across the page, rather than summing up every square as we go.
-// And in fact, to move down a line, we need only roll one set of squares in
-// and one set out.
+use aoc2018::{Matrix, SummedArea};
 
 const SIZE: usize = 300;
 
@@ -43,15 +33,16 @@ pub fn main() {
 }
 
 struct Map {
-    /// Power levels indexed by `point(x, y)`.
-    /// In the problem description indices are 1-based but for simplicity
-    /// these are 1-based, and we convert on output.
-    p: Matrix<i32>,
+    /// Summed-area table of the fuel cells' power levels, so any square's
+    /// total can be read off directly. Cells are at 0-based `point(x, y)`;
+    /// the puzzle numbers them from 1, so coordinates are converted on
+    /// output.
+    sums: SummedArea<i32>,
 }
 
 impl Map {
     pub fn new(grid: i32) -> Map {
-        let mut p = Matrix::new(SIZE + 1, SIZE + 1, i32::min_value());
+        let mut p = Matrix::new(SIZE, SIZE, 0);
         for x in 0..SIZE {
             for y in 0..SIZE {
                 // Find the fuel cell's rack ID, which is its X coordinate
@@ -72,39 +63,24 @@ impl Map {
                 p[point(x, y)] = pwr;
             }
         }
-        Map { p }
+        Map {
+            sums: p.summed_area(),
+        }
     }
 
     #[cfg(test)]
     pub fn get(&self, c: (usize, usize)) -> i32 {
-        self.p[point(c.0, c.1)]
+        self.squaresum(c, 1)
     }
 
+    #[cfg(test)]
     pub fn squaresum(&self, c: (usize, usize), sqsz: usize) -> i32 {
-        let mut s: i32 = 0;
-        for x in c.0..(c.0 + sqsz) {
-            for y in c.1..(c.1 + sqsz) {
-                s += self.p[point(x, y)];
-            }
-        }
-        s
+        self.sums.sum(point(c.0, c.1), sqsz, sqsz)
     }
 
     pub fn hottest(&self, sqsz: usize) -> ((usize, usize), i32) {
-        let mut best_power: i32 = i32::min_value();
-        let mut best_point: (usize, usize) = (0, 0);
-
-        for x in 0..(SIZE - sqsz) {
-            for y in 0..(SIZE - sqsz) {
-                let p = (x, y);
-                let pwr = self.squaresum(p, sqsz);
-                if pwr > best_power {
-                    best_power = pwr;
-                    best_point = p;
-                }
-            }
-        }
-        ((best_point.0 + 1, best_point.1 + 1), best_power)
+        let (p, power) = self.sums.max_square(sqsz).unwrap();
+        ((p.x + 1, p.y + 1), power)
     }
 
     /// Find the square within the map that has the largest total power.
@@ -112,81 +88,8 @@ impl Map {
     /// Returns the (x,y) coords of the top of that square, its size, and the
     /// total power.
     pub fn hottest_square(&self) -> ((usize, usize), usize, i32) {
-        // General approach here is to work up through squares of increasing
-        // sizes, starting from 1.
-        //
-        // As we go along, we simply remember the origin, size, and total power
-        // of the most powerful cell we've seen.
-        //
-        // As we go along we remember the sum of power of strips of size
-        // S running vertically down from every possible cell, and also
-        // horizontally across from every possible cell. (Not from those
-        // within S of the boundary.) We also remember the sum of power
-        // for squares of size S in every possible position.
-        //
-        // To start with at S=1 these are all trivially the value of each
-        // cell itself.
-        //
-        // To proceed to S+1, we first extend each of the squares
-        // by adding in the vertical strip for the next column, and the
-        // horizontal strip for the next row, and the single cell in the
-        // corner between them. Then, we extend the strips by adding in
-        // one more square in each direction.
-
-        let mut sqs = Matrix::new(SIZE, SIZE, 0i32);
-        let mut vstr = Matrix::new(SIZE, SIZE, 0i32);
-        let mut hstr = Matrix::new(SIZE, SIZE, 0i32);
-        let mut best_p = point(0, 0);
-        let mut best_power = i32::min_value();
-        let mut best_size = 1;
-
-        // Start at size 1: everything is simply the contents of that cell.
-        for x in 0..SIZE {
-            for y in 0..SIZE {
-                let p = point(x, y);
-                vstr[p] = self.p[p];
-                hstr[p] = self.p[p];
-                sqs[p] = self.p[p];
-                if self.p[p] > best_power {
-                    best_power = self.p[p];
-                    best_p = p;
-                }
-            }
-        }
-
-        for sz in 2..SIZE {
-            // First, grow all the squares (that can still fit) by adding a strip
-            // of sz-1 to the right and one to the bottom and one cell in the
-            // corner.
-            //
-            // Squares that don't fit are just ignored henceforth.
-            let osz = sz - 1;
-            for x in 0..(SIZE - sz) {
-                for y in 0..(SIZE - sz) {
-                    let p = point(x, y);
-                    sqs[p] += vstr[point(x + osz, y)]
-                        + hstr[point(x, y + osz)]
-                        + self.p[point(x + osz, y + osz)];
-                    if sqs[p] > best_power {
-                        best_power = sqs[p];
-                        best_p = p;
-                        best_size = sz;
-                    }
-                }
-            }
-
-            // Now, grow all the strips (that can still fit) from osz to sz
-            // by adding one more square.
-            for x in 0..(SIZE - sz) {
-                for y in 0..(SIZE - sz) {
-                    let p = point(x, y);
-                    vstr[p] += self.p[point(x, y + osz)];
-                    hstr[p] += self.p[point(x + osz, y)];
-                }
-            }
-        }
-
-        ((best_p.x + 1, best_p.y + 1), best_size, best_power)
+        let (p, size, power) = self.sums.max_square_any().unwrap();
+        ((p.x + 1, p.y + 1), size, power)
     }
 }
 
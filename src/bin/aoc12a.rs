@@ -23,13 +23,15 @@
 //! The furthest it can possibly propagate out to the left or right is
 //! two pots per generation.
 
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fs::File;
 use std::io::Read;
 use std::ops::Range;
 use std::rc::Rc;
 
+use aoc2018::Tape;
+
 pub fn main() {
     let mut s = String::new();
     File::open("input/input12.txt")
@@ -38,7 +40,7 @@ pub fn main() {
         .unwrap();
     let mut p: Pots = s.parse().unwrap();
     for _i in 0..20 {
-        p = p.step();
+        p.step();
     }
     println!("result = {}", p.magic());
 }
@@ -53,8 +55,8 @@ fn from_b(c: u8) -> bool {
 
 #[derive(Clone)]
 struct Pots {
-    /// Indices of pots that are occupied.
-    pots: BTreeSet<isize>,
+    /// Which pots are occupied.
+    pots: Tape<bool>,
     /// Map of instructions from 5-bool context to new results
     inst: Rc<BTreeMap<[bool; 5], bool>>,
 }
@@ -94,38 +96,24 @@ impl std::str::FromStr for Pots {
 }
 
 impl Pots {
-    fn parse_first_line(s: &str) -> BTreeSet<isize> {
+    fn parse_first_line(s: &str) -> Tape<bool> {
         let (prefix, bs) = s.split_at(15);
         assert_eq!(prefix, "initial state: ");
-        let mut pots = BTreeSet::new();
-        for (i, c) in bs.bytes().map(from_b).enumerate() {
-            if c {
-                pots.insert(i as isize);
-            }
-        }
-        pots
-    }
-
-    pub fn set(&mut self, i: isize, b: bool) {
-        if b {
-            self.pots.insert(i);
-        } else {
-            self.pots.remove(&i);
-        }
+        Tape::new(bs.bytes().map(from_b).collect(), false, 2)
     }
 
     pub fn get(&self, i: isize) -> bool {
-        self.pots.contains(&i)
+        *self.pots.get(i)
     }
 
     /// Number of the highest pot that's set
     fn right(&self) -> isize {
-        *self.pots.iter().next_back().unwrap()
+        self.pots.range().end - 1
     }
 
     /// Number of the lowest pot that's set
     fn left(&self) -> isize {
-        *self.pots.iter().next().unwrap()
+        self.pots.range().start
     }
 
     pub fn format_pots(&self, r: Range<isize>) -> String {
@@ -145,25 +133,15 @@ impl Pots {
         a
     }
 
-    /// Produce new pots for the next step
-    pub fn step(&self) -> Pots {
-        let mut pots = BTreeSet::new();
-        for i in (self.left() - 2)..=(self.right() + 2) {
-            let a = self.around(i);
-            let n = self.inst.get(&a).unwrap_or(&false);
-            if *n {
-                pots.insert(i);
-            }
-        }
-        Pots {
-            pots,
-            inst: self.inst.clone(),
-        }
+    /// Advance to the next generation.
+    pub fn step(&mut self) {
+        let inst = &self.inst;
+        self.pots.step(|a| *inst.get(a).unwrap_or(&false));
     }
 
     /// Return the sum of pot-numbers that have a plant.
     pub fn magic(&self) -> isize {
-        self.pots.iter().sum()
+        self.pots.iter().filter(|(_, p)| **p).map(|(i, _)| i).sum()
     }
 }
 
@@ -198,19 +176,20 @@ initial state: #..#.#..##......###...###
             p.format_pots(-3..36),
             "...#..#.#..##......###...###..........."
         );
-        assert_eq!(p.get(20), false);
-        assert_eq!(p.get(22), true);
+        assert!(!p.get(20));
+        assert!(p.get(22));
         assert_eq!(p.around(0), [false, false, true, false, false]);
         assert_eq!(p.around(2), [true, false, false, true, false]);
 
-        let mut p1 = p.step();
+        let mut p1 = p.clone();
+        p1.step();
         assert_eq!(
             p1.format_pots(-3..36),
             "...#...#....#.....#..#..#..#..........."
         );
 
         for _i in 2..=20 {
-            p1 = p1.step();
+            p1.step();
         }
         assert_eq!(
             p1.format_pots(-3..36),
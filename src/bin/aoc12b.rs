@@ -22,50 +22,49 @@
 //! The furthest it can possibly propagate out to the left or right is
 //! two pots per generation.
 
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 use std::rc::Rc;
 
+use aoc2018::Tape;
+
 pub fn main() {
     let mut s = String::new();
     File::open("input/input12.txt")
         .unwrap()
         .read_to_string(&mut s)
         .unwrap();
-    let mut p = Pots::from_string(&s);
-    let n_steps = 50_000_000_000u64;
+    println!(
+        "result = {}",
+        sum_after(Pots::from_string(&s), 50_000_000_000)
+    );
+}
+
+/// The sum of the numbers of pots with plants after `n_steps` generations,
+/// assuming the pattern eventually settles into moving one pot right per
+/// generation.
+fn sum_after(mut p: Pots, n_steps: u64) -> u64 {
     let mut i = 0;
+    // The previous generation's pots and leftmost pot number: all that's
+    // needed to spot the shift, without copying the whole tape each time.
+    let mut prev = Vec::new();
     loop {
-        // 0.. {
-        println!(
-            "left={}  {}",
-            p.left(),
-            p.format_pots(p.left()..=p.right())
-        );
-        let next_p = p.step();
-        // if i % 100_000 == 0 {
-        //     println!("i={:>10} p={:?}", i, p);
-        // }
-        // if next_p == p {
-        //     break
-        // } else {
-        //     p = next_p
-        // }
-        if next_p.is_right_shift(&p) {
+        println!("left={}  {}", p.left(), p.format_pots(p.left()..=p.right()));
+        prev.clear();
+        prev.extend_from_slice(p.pots.cells());
+        let prev_left = p.left();
+        p.step();
+        i += 1;
+        if p.is_right_shift(&prev, prev_left) {
             println!("found stable right shift");
             break;
         }
-        p = next_p;
-        i += 1;
     }
     // The sum of number of pots that have a plant is, the current sum, plus the remaining steps times
     // the number of pots.
-    println!(
-        "result = {}",
-        p.magic() as u64 + (n_steps - i) * p.pots.len() as u64
-    );
+    p.magic() as u64 + (n_steps - i) * p.count() as u64
 }
 
 fn from_b(c: u8) -> bool {
@@ -78,8 +77,8 @@ fn from_b(c: u8) -> bool {
 
 #[derive(Clone, PartialEq, Eq)]
 struct Pots {
-    /// Indices of pots that are occupied.
-    pub pots: BTreeSet<isize>,
+    /// Which pots are occupied.
+    pub pots: Tape<bool>,
     /// Map of instructions from 5-bool context to new results
     pub inst: Rc<BTreeMap<[bool; 5], bool>>,
 }
@@ -115,41 +114,27 @@ impl Pots {
         }
     }
 
-    fn parse_first_line(s: &str) -> BTreeSet<isize> {
+    fn parse_first_line(s: &str) -> Tape<bool> {
         let (prefix, bs) = s.split_at(15);
         assert_eq!(prefix, "initial state: ");
-        let mut pots = BTreeSet::new();
-        for (i, c) in bs.bytes().map(from_b).enumerate() {
-            if c {
-                pots.insert(i as isize);
-            }
-        }
-        pots
-    }
-
-    pub fn set(&mut self, i: isize, b: bool) {
-        if b {
-            self.pots.insert(i);
-        } else {
-            self.pots.remove(&i);
-        }
+        Tape::new(bs.bytes().map(from_b).collect(), false, 2)
     }
 
     pub fn get(&self, i: isize) -> bool {
-        self.pots.contains(&i)
+        *self.pots.get(i)
     }
 
     /// Number of the highest pot that's set
     pub fn right(&self) -> isize {
-        *self.pots.iter().next_back().unwrap()
+        self.pots.range().end - 1
     }
 
     /// Number of the lowest pot that's set
     pub fn left(&self) -> isize {
-        *self.pots.iter().next().unwrap()
+        self.pots.range().start
     }
 
-    pub fn format_pots<R: IntoIterator<Item=isize>>(&self, r: R) -> String {
+    pub fn format_pots<R: IntoIterator<Item = isize>>(&self, r: R) -> String {
         let mut s = String::new();
         for i in r {
             s.push(if self.get(i) { '#' } else { '.' });
@@ -160,44 +145,32 @@ impl Pots {
     /// Return the values of the 5 pots around i
     fn around(&self, i: isize) -> [bool; 5] {
         let mut a = [false; 5];
-        for j in 0..5 {
-            a[j] = self.get(i + (j as isize) - 2);
+        for (j, aj) in a.iter_mut().enumerate() {
+            *aj = self.get(i + (j as isize) - 2);
         }
         a
     }
 
-    /// Produce new pots for the next step
-    pub fn step(&self) -> Pots {
-        let mut pots = BTreeSet::new();
-        for i in (self.left() - 3)..=(self.right() + 3) {
-            let a = self.around(i);
-            let n = self.inst.get(&a).unwrap_or(&false);
-            if *n {
-                pots.insert(i);
-            }
-        }
-        Pots {
-            pots,
-            inst: self.inst.clone(),
-        }
+    /// Advance to the next generation.
+    pub fn step(&mut self) {
+        let inst = &self.inst;
+        self.pots.step(|a| *inst.get(a).unwrap_or(&false));
     }
 
     /// Return the sum of pot-numbers that have a plant.
     pub fn magic(&self) -> isize {
-        self.pots.iter().sum()
+        self.pots.iter().filter(|(_, p)| **p).map(|(i, _)| i).sum()
     }
 
-    /// True if self is `other` shifted one space to the right.
-    pub fn is_right_shift(&self, other: &Pots) -> bool {
-        if self.pots.len() != other.pots.len() {
-            return false;
-        }
-        for i in self.pots.iter() {
-            if !other.pots.contains(&(i - 1)) {
-                return false;
-            }
-        }
-        true
+    /// Number of pots that have a plant.
+    pub fn count(&self) -> usize {
+        self.pots.cells().iter().filter(|p| **p).count()
+    }
+
+    /// True if self is the pots `cells`, starting at pot `left`, shifted
+    /// one space to the right.
+    pub fn is_right_shift(&self, cells: &[bool], left: isize) -> bool {
+        self.pots.cells() == cells && self.left() == left + 1
     }
 }
 
@@ -232,19 +205,20 @@ initial state: #..#.#..##......###...###
             p.format_pots(-3..36),
             "...#..#.#..##......###...###..........."
         );
-        assert_eq!(p.get(20), false);
-        assert_eq!(p.get(22), true);
+        assert!(!p.get(20));
+        assert!(p.get(22));
         assert_eq!(p.around(0), [false, false, true, false, false]);
         assert_eq!(p.around(2), [true, false, false, true, false]);
 
-        let mut p1 = p.step();
+        let mut p1 = p.clone();
+        p1.step();
         assert_eq!(
             p1.format_pots(-3..36),
             "...#...#....#.....#..#..#..#..........."
         );
 
         for _i in 2..=20 {
-            p1 = p1.step();
+            p1.step();
         }
         assert_eq!(
             p1.format_pots(-3..36),
@@ -292,10 +266,17 @@ initial state: #.##...#.##.##....#.##...#.##.##...#.##.##.##.##.##.##.##.##...#.
 #..## => .
 .##.. => #
 ");
-        println!("{:?}", p.pots);
-        let p1 = p.step();
-        println!("{:?}", p1.pots);
-        let p1 = p1.step();
-        println!("{:?}", p1.pots);
+        println!("{:?}", p);
+        let mut p1 = p.clone();
+        p1.step();
+        println!("{:?}", p1);
+        p1.step();
+        println!("{:?}", p1);
+    }
+
+    #[test]
+    fn solve() {
+        let p = Pots::from_string(include_str!("../../input/input12.txt"));
+        assert_eq!(sum_after(p, 50_000_000_000), 3900000002212);
     }
 }
@@ -31,6 +31,8 @@ use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::Read;
 
+use aoc2018::{point, Glyph, Matrix};
+
 /// Coordinates as (y, x).
 type Coords = (usize, usize);
 
@@ -71,7 +73,6 @@ impl Direction {
         }
     }
 
-    #[allow(dead_code)]
     pub fn to_char(self) -> char {
         match self {
             Direction::Up => '^',
@@ -81,10 +82,10 @@ impl Direction {
         }
     }
 
-    pub fn plain_track(self) -> char {
+    pub fn plain_track(self) -> Square {
         match self {
-            Direction::Up | Direction::Down => '|',
-            Direction::Left | Direction::Right => '-',
+            Direction::Up | Direction::Down => Square::Vertical,
+            Direction::Left | Direction::Right => Square::Horizontal,
         }
     }
 
@@ -116,6 +117,49 @@ impl Direction {
     }
 }
 
+/// One square of the map, as drawn in the puzzle input.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Square {
+    Empty,
+    Vertical,
+    Horizontal,
+    /// A `/` curve.
+    Slash,
+    /// A `\` curve.
+    Backslash,
+    Intersection,
+    /// A cart on a straight piece of track, which appears only in the input
+    /// and when rendering.
+    Cart(Direction),
+}
+
+impl Glyph for Square {
+    fn from_char(c: char) -> Option<Square> {
+        match c {
+            ' ' => Some(Square::Empty),
+            '|' => Some(Square::Vertical),
+            '-' => Some(Square::Horizontal),
+            '/' => Some(Square::Slash),
+            '\\' => Some(Square::Backslash),
+            '+' => Some(Square::Intersection),
+            '<' | '>' | 'v' | '^' => Some(Square::Cart(Direction::from_char(c))),
+            _ => None,
+        }
+    }
+
+    fn to_char(&self) -> char {
+        match self {
+            Square::Empty => ' ',
+            Square::Vertical => '|',
+            Square::Horizontal => '-',
+            Square::Slash => '/',
+            Square::Backslash => '\\',
+            Square::Intersection => '+',
+            Square::Cart(dir) => dir.to_char(),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 struct Cart {
     /// Number of intersections this has passed, starting at 0.
@@ -145,32 +189,32 @@ impl Cart {
 
         // Now, maybe turn, depending on what's at the new point, and our entry
         // direction, and our turn counter.
-        let c = m.look((y, x));
-        let dir = match c {
-            '-' | '|' => self.dir,
-            '+' => match self.inters {
+        let sq = m.look((y, x));
+        let dir = match sq {
+            Square::Horizontal | Square::Vertical => self.dir,
+            Square::Intersection => match self.inters {
                 0 => self.dir.turn_left(),
                 1 => self.dir,
                 2 => self.dir.turn_right(),
                 other => panic!("bad inters {:?}", other),
             },
-            '\\' => match self.dir {
+            Square::Backslash => match self.dir {
                 Direction::Up => Direction::Left,
                 Direction::Down => Direction::Right,
                 Direction::Left => Direction::Up,
                 Direction::Right => Direction::Down,
             },
-            '/' => match self.dir {
+            Square::Slash => match self.dir {
                 Direction::Up => Direction::Right,
                 Direction::Down => Direction::Left,
                 Direction::Left => Direction::Down,
                 Direction::Right => Direction::Up,
             },
-            other => panic!("unimplemented map character {:?}", other),
+            other => panic!("cart ran onto {:?} at {:?}", other, (y, x)),
         };
 
         // Increment intersection counter if passing through an intersection
-        let inters = if c == '+' {
+        let inters = if sq == Square::Intersection {
             (self.inters + 1) % 3
         } else {
             self.inters
@@ -182,10 +226,8 @@ impl Cart {
 
 #[derive(Clone, Eq, PartialEq)]
 struct Map {
-    /// Indexed by [y][x], a map of the track with no carts present.
-    m: Vec<Vec<char>>,
-    w: usize,
-    h: usize,
+    /// A map of the track with no carts present.
+    m: Matrix<Square>,
 
     /// Indexed by the current position of the cart as [y][x], and the contents
     /// describe the state of the cart.  The index is in this order because the
@@ -197,36 +239,17 @@ struct Map {
 
 impl Map {
     pub fn from_string(s: &str) -> Map {
-        let mut m: Vec<Vec<char>> = Vec::new();
+        let mut m = Matrix::<Square>::parse_ascii(s).unwrap_or_else(|e| panic!("{}", e));
         let mut carts = BTreeMap::new();
-        for (y, l) in s.lines().enumerate() {
-            let mut row = Vec::new();
-            let lw = l.len();
-            if !m.is_empty() {
-                assert_eq!(lw, m[0].len(), "{:?} different length from {:?}", l, m[0]);
-            }
-            for (x, c) in l.chars().enumerate() {
-                // If there's a cart, remember that location and also strip it out.
-                let c = match c {
-                    '<' | '>' | 'v' | '^' => {
-                        let dir = Direction::from_char(c);
-                        let pos = (y, x);
-                        carts.insert(pos, Cart::new(dir, pos));
-                        dir.plain_track()
-                    }
-                    _ => c,
-                };
-                row.push(c);
+        for p in m.iter_points().collect::<Vec<_>>() {
+            // If there's a cart, remember that location and also strip it out.
+            if let Square::Cart(dir) = m[p] {
+                let pos = (p.y, p.x);
+                carts.insert(pos, Cart::new(dir, pos));
+                m[p] = dir.plain_track();
             }
-            m.push(row)
-        }
-        Map {
-            w: m[0].len(),
-            h: m.len(),
-            m,
-            carts,
-            tick: 1,
         }
+        Map { m, carts, tick: 1 }
     }
 
     /// Take one step.
@@ -248,7 +271,7 @@ impl Map {
 
         let op: Vec<(usize, usize)> = carts.keys().cloned().collect();
         for p in op.iter() {
-            if let Some(oldc) = carts.remove(&p) {
+            if let Some(oldc) = carts.remove(p) {
                 let newc = oldc.step(self);
                 // println!("step {:?} to {:?}", oldc, newc);
                 let newp = (newc.y, newc.x);
@@ -287,23 +310,16 @@ impl Map {
 
     #[cfg(test)]
     pub fn render(&self) -> String {
-        let mut s = String::with_capacity(self.h * (self.w + 1));
-        for y in 0..self.h {
-            for x in 0..self.w {
-                if let Some(cart) = self.carts.get(&(y, x)) {
-                    s.push(cart.dir.to_char())
-                } else {
-                    s.push(self.m[y][x])
-                }
-            }
-            s.push('\n');
+        let mut m = self.m.clone();
+        for cart in self.carts.values() {
+            m[point(cart.x, cart.y)] = Square::Cart(cart.dir);
         }
-        s
+        m.render_ascii()
     }
 
-    /// Get the underlying map character
-    pub fn look(&self, p: (usize, usize)) -> char {
-        self.m[p.0][p.1]
+    /// Get the underlying map square
+    pub fn look(&self, p: (usize, usize)) -> Square {
+        self.m[point(p.1, p.0)]
     }
 }
 
@@ -327,8 +343,7 @@ mod test {
   \------/   
 "[1..];
         let mut m = Map::from_string(mapstr1);
-        assert_eq!(m.w, 13);
-        assert_eq!(m.h, 6);
+        assert_eq!((m.m.width(), m.m.height()), (13, 6));
         assert_eq!(m.tick, 1);
         assert_eq!(m.render(), mapstr1);
 
@@ -405,7 +420,7 @@ mod test {
     #[test]
     fn linear() {
         let mut m = Map::from_string(
-            &"\
+            "\
 |
 v
 |
@@ -19,8 +19,8 @@
 use std::fs::File;
 use std::io::Read;
 
-use aoc2018::matrix::Matrix;
-use aoc2018::{point, Point};
+use aoc2018::matrix::{Glyph, Matrix};
+use aoc2018::{all_distances, nearest, point, Point};
 
 const INITIAL_HP: usize = 200;
 const GOBLIN_POWER: usize = 3;
@@ -56,18 +56,18 @@ enum Thing {
 
 use self::Thing::*;
 
-impl Thing {
-    pub fn from_char(ch: char) -> Thing {
+impl Glyph for Thing {
+    fn from_char(ch: char) -> Option<Thing> {
         match ch {
-            '.' => Thing::Empty,
-            '#' => Thing::Wall,
-            'E' => Thing::Elf(INITIAL_HP),
-            'G' => Thing::Goblin(INITIAL_HP),
-            other => panic!("unexpected character {:?}", other),
+            '.' => Some(Thing::Empty),
+            '#' => Some(Thing::Wall),
+            'E' => Some(Thing::Elf(INITIAL_HP)),
+            'G' => Some(Thing::Goblin(INITIAL_HP)),
+            _ => None,
         }
     }
 
-    pub fn to_char(self) -> char {
+    fn to_char(&self) -> char {
         match self {
             Thing::Empty => '.',
             Thing::Wall => '#',
@@ -75,14 +75,15 @@ impl Thing {
             Thing::Goblin(_) => 'G',
         }
     }
+}
 
+impl Thing {
     /// The enemy race for creatures (only).
     pub fn is_enemy(&self, other: &Thing) -> bool {
-        match (self, other) {
-            (Thing::Goblin(_), Thing::Elf(_)) => true,
-            (Thing::Elf(_), Thing::Goblin(_)) => true,
-            _ => false,
-        }
+        matches!(
+            (self, other),
+            (Thing::Goblin(_), Thing::Elf(_)) | (Thing::Elf(_), Thing::Goblin(_))
+        )
     }
 
     pub fn is_empty(self) -> bool {
@@ -90,25 +91,15 @@ impl Thing {
     }
 
     pub fn is_creature(&self) -> bool {
-        match self {
-            Thing::Elf(_) => true,
-            Thing::Goblin(_) => true,
-            _ => false,
-        }
+        matches!(self, Thing::Elf(_) | Thing::Goblin(_))
     }
 
     pub fn is_goblin(&self) -> bool {
-        match self {
-            Goblin(_) => true,
-            _ => false,
-        }
+        matches!(self, Goblin(_))
     }
 
     pub fn is_elf(&self) -> bool {
-        match self {
-            Elf(_) => true,
-            _ => false,
-        }
+        matches!(self, Elf(_))
     }
     pub fn creature_hp(&self) -> Option<usize> {
         match self {
@@ -130,25 +121,9 @@ struct Map {
 
 impl Map {
     pub fn from_string(s: &str) -> Map {
-        let mut mb = Matrix::<Thing>::from_rows();
-        let mut n_elf = 0;
-        let mut n_goblin = 0;
-        for l in s.lines() {
-            let r: Vec<Thing> = l.chars().map(Thing::from_char).collect();
-            mb.add_row(&r);
-            for i in r {
-                match i {
-                    Elf(_) => {
-                        n_elf += 1;
-                    }
-                    Goblin(_) => {
-                        n_goblin += 1;
-                    }
-                    _ => (),
-                }
-            }
-        }
-        let m = mb.finish();
+        let m = Matrix::<Thing>::parse_ascii(s).unwrap_or_else(|e| panic!("{}", e));
+        let n_elf = m.values().filter(|t| t.is_elf()).count();
+        let n_goblin = m.values().filter(|t| t.is_goblin()).count();
         Map {
             w: m.width(),
             h: m.height(),
@@ -160,29 +135,10 @@ impl Map {
         }
     }
 
-    /// Return all valid neighbors of a point, in reading order.
-    pub fn neighbors(&self, p: Point) -> Vec<Point> {
-        let mut v = Vec::with_capacity(4);
-        let x = p.x;
-        let y = p.y;
-        if y > 0 {
-            v.push(Point { x, y: y - 1 })
-        }
-        if x > 0 {
-            v.push(Point { x: x - 1, y })
-        }
-        if x < self.w - 1 {
-            v.push(Point { x: x + 1, y })
-        }
-        if y < self.h - 1 {
-            v.push(Point { x, y: y + 1 })
-        }
-        v
-    }
-
     /// Return the empty neighbors of a point, in reading order.
     pub fn empty_neighbors(&self, p: Point) -> Vec<Point> {
-        self.neighbors(p)
+        self.m
+            .neighbors4(p)
             .into_iter()
             .filter(|p| self.thing_at(*p).is_empty())
             .collect()
@@ -194,8 +150,8 @@ impl Map {
     /// order.
     pub fn target(&mut self, ap: Point, attacker: &Thing) -> Option<Point> {
         let mut best_p: Option<Point> = None;
-        let mut best_hp: usize = usize::max_value();
-        for p in self.neighbors(ap).into_iter() {
+        let mut best_hp: usize = usize::MAX;
+        for p in self.m.neighbors4(ap).into_iter() {
             let thingp = self.thing_at(p);
             if let Some(hp) = thingp.creature_hp() {
                 if thingp.is_enemy(attacker) && hp < best_hp {
@@ -256,15 +212,11 @@ impl Map {
     }
 
     pub fn render(&self) -> String {
-        let mut s = format!("Round: {}\n", self.completed_rounds);
-        for y in 0..self.h {
-            for x in 0..self.w {
-                let p = point(x, y);
-                s.push(self.thing_at(p).to_char());
-            }
-            s.push('\n');
-        }
-        s
+        format!(
+            "Round: {}\n{}",
+            self.completed_rounds,
+            self.m.render_ascii()
+        )
     }
 
     fn annihilated(&self) -> bool {
@@ -305,7 +257,7 @@ impl Map {
                 if let Some(tp) = self.target(cp, &th) {
                     // Attack immediately without needing to move
                     self.hurt(tp);
-                } else if let Some(r) = Routing::new(&self, cp) {
+                } else if let Some(r) = Routing::new(self, cp) {
                     // Move, then try to attack.
                     // println!(
                     //     "move {:?} from {:?} to {:?} towards {:?}, {} steps",
@@ -357,92 +309,46 @@ struct Routing {
     dist: usize,
 }
 
-/// New approach on finding the best destination and first step towards it,
-/// given a set of interesting destinations (from which you can attack enemies)
-/// and a starting point.
-///
-/// Dests must be in sorted order.
+/// Find the best destination and first step towards it: the nearest empty
+/// square from which an enemy can be attacked, or the first in reading order
+/// if several are equally near, and then the first step in reading order
+/// along a shortest path to it.
 impl Routing {
     pub fn new(m: &Map, origin: Point) -> Option<Routing> {
-        // All the points numbered in the last round, and needing to be
-        // checked in the next round.
-        let mut last = vec![origin];
-        let mut d = Matrix::new(m.w, m.h, None);
-        let mut dist = 0;
         let actor = m.thing_at(origin);
         assert!(actor.is_creature());
-        // Points neighboring an enemy.
-        let mut ends = Vec::new();
-        d[origin] = Some(0);
-        //
-        // First, flood-fill out from the starting point. The starting point is
-        // distance 0. Every empty immediate neighbor, not yet assigned a distance,
-        // is distance 1. Etc.
-        //
-        // Do this by remembering all the squares that were numbered in the previous
-        // round, and come back to visit all their empty, un-numbered neighbors.
-        //
-        // Stop after the complete round in which you assign a distance to any of
-        // the possible destinations, or if we run out of reachable empty
-        // squares to number.
-
-        // TODO: We could always visit potential destinations in point order,
-        // rather than grouped around `lp`, and therefore not need to keep
-        // a list of `dests` that are later filtered.
-
         // println!("routing from {:?} at {:?}", actor, origin);
 
-        while ends.is_empty() && !last.is_empty() {
-            let mut next = Vec::new();
-            dist += 1;
-            for lp in last.into_iter() {
-                for np in m.neighbors(lp).into_iter() {
-                    if actor.is_enemy(&m.thing_at(np)) {
-                        // lp neighbors an enemy; we could stop here.
-                        // println!("found enemy at {:?} from {:?} after {:?}", np, lp, dist);
-                        ends.push(lp);
-                    } else if m.thing_at(np).is_empty() && d[np].is_none() {
-                        // We could move to np along this path; let's see if
-                        // we could move further
-                        d[np] = Some(dist);
-                        next.push(np);
-                    }
-                }
-            }
-            last = next;
-        }
-
-        // Chosen destination is the first one, in order.
-        if ends.is_empty() {
-            // Filled as much of the map as we can, without finding any
-            // reachable enemies. Let's stop.
-            return None;
+        let mut nbrs = |p: Point| {
+            m.empty_neighbors(p)
+                .into_iter()
+                .map(|np| (np, 1))
+                .collect::<Vec<_>>()
+        };
+        let near_enemy = |p: Point| {
+            m.m.neighbors4(p)
+                .into_iter()
+                .any(|np| actor.is_enemy(&m.thing_at(np)))
+        };
+        let route = nearest(origin, &mut nbrs, near_enemy)?;
+        let chosen = *route.states.last().unwrap();
+
+        // The route found might not start with the right step, so measure
+        // distances back from the chosen destination and take the first
+        // neighbor that's on a shortest path.
+        let step = if chosen == origin {
+            origin
+        } else {
+            let back = all_distances(chosen, &mut nbrs);
+            m.m.neighbors4(origin)
+                .into_iter()
+                .find(|np| back.get(np) == Some(&(route.distance - 1)))
+                .unwrap()
         };
-        let chosen = *ends.iter().min().unwrap();
-
-        // Now walk back from that chosen point towards the origin, taking at each
-        // step the smallest numbered point. Since we only ever numbered
-        // the minimum number of squares to reach the destination, any path
-        // we find must be a minimal path, and if we always prefer to go to
-        // the smallest-numbered neighbors we must arrive at the smallest
-        // destination.
-        let mut j = dist - 1;
-        let mut backp = chosen;
-        'backup: while j > 1 {
-            debug_assert_eq!(d[backp], Some(j));
-            for np in m.empty_neighbors(backp) {
-                if d[np] == Some(j - 1) {
-                    j -= 1;
-                    backp = np;
-                    continue 'backup;
-                }
-            }
-            panic!("No backup step found from {:?}", backp);
-        }
         Some(Routing {
             chosen,
-            step: backp,
-            dist,
+            step,
+            dist: route.distance as usize,
         })
     }
 }
@@ -462,7 +368,7 @@ mod test {
              #######\n",
         );
         assert_eq!(
-            m.neighbors(point(0, 0)),
+            m.m.neighbors4(point(0, 0)),
             vec![Point { x: 1, y: 0 }, Point { x: 0, y: 1 }]
         );
     }
@@ -19,63 +19,23 @@
 use std::collections::BTreeMap;
 use std::ops::Range;
 
-type Reg = usize;
-const OPS: Range<Reg> = 0..16;
-
-/// An instruction
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd)]
-pub enum Inst {
-    Addr,
-    Addi,
-    Mulr,
-    Muli,
-    Banr,
-    Bani,
-    Borr,
-    Bori,
-    Setr,
-    Seti,
-    Gtir,
-    Gtri,
-    Gtrr,
-    Eqir,
-    Eqri,
-    Eqrr,
-}
-use self::Inst::*;
-
-impl Inst {
-    pub fn apply(self, r: &[Reg; 4], args: &[Reg]) -> [Reg; 4] {
-        debug_assert_eq!(args.len(), 4);
-        let (a, b, c) = (args[1], args[2], args[3]);
-        let mut after = *r;
-        // Happily in Rust if you cast a bool to an int, it goes to 1 and 0.
-        after[c] = match self {
-            Muli => r[a] * b,
-            Mulr => r[a] * r[b],
-            Addr => r[a] + r[b],
-            Addi => r[a] + b,
-            Banr => r[a] & r[b],
-            Bani => r[a] & b,
-            Borr => r[a] | r[b],
-            Bori => r[a] | b,
-            Setr => r[a],
-            Seti => a,
-            Gtir => (a > r[b]) as Reg,
-            Gtri => (r[a] > b) as Reg,
-            Gtrr => (r[a] > r[b]) as Reg,
-            Eqir => (a == r[b]) as Reg,
-            Eqri => (r[a] == b) as Reg,
-            Eqrr => (r[a] == r[b]) as Reg,
-        };
-        after
-    }
+use aoc2018::elfcode::{Inst, Opcode, Reg, Word};
+
+const OPS: Range<usize> = 0..16;
+
+/// How many possible assignments to report when the samples don't pin
+/// down just one.
+const MAX_EXAMPLES: usize = 32;
 
-    pub fn all() -> &'static [Inst] {
-        &[
-            Muli, Mulr, Addr, Addi, Banr, Bani, Borr, Bori, Setr, Seti, Gtir, Gtri, Gtrr, Eqir,
-            Eqri, Eqrr,
-        ]
+/// Build the instruction for an encoded opcode number followed by three
+/// arguments.
+fn decode_inst(opcode: Opcode, ops: &[Reg]) -> Inst {
+    debug_assert_eq!(ops.len(), 4);
+    Inst {
+        opcode,
+        a: ops[1],
+        b: ops[2],
+        c: ops[3],
     }
 }
 
@@ -133,12 +93,16 @@ impl Sample {
     }
 
     /// Return the instructions that could possibly have generated this output,
-    /// from this input.
-    pub fn possible_inst(&self) -> Vec<Inst> {
+    /// from this input. An instruction that would fault can't have.
+    pub fn possible_inst(&self) -> Vec<Opcode> {
         let mut v = Vec::with_capacity(16);
-        for inst in Inst::all().iter() {
-            if inst.apply(&self.before, &self.ops) == self.after {
-                v.push(*inst);
+        for opcode in Opcode::all().iter() {
+            let mut r = self.before;
+            let ok = decode_inst(*opcode, &self.ops)
+                .exec(Word::default(), &mut r)
+                .is_ok();
+            if ok && r == self.after {
+                v.push(*opcode);
             }
         }
         v
@@ -148,75 +112,137 @@ impl Sample {
 /// Given some samples, work out which opcodes could possibly have what effect.
 #[derive(Debug, Default)]
 pub struct Infer {
-    /// Potential Opcode->Instructions mapping that have been observed.
-    possible: Vec<Vec<Inst>>,
+    /// For each opcode number, the instructions consistent with every sample
+    /// using that number.
+    possible: Vec<Vec<Opcode>>,
+}
 
-    /// Opcode-instruction mapping that's been unambiguously determined.
-    certain: Vec<Option<Inst>>,
+/// The outcome of solving for the opcode numbers.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Solution {
+    /// Exactly one assignment fits the samples.
+    Unique(Decode),
+    /// More than one assignment fits. Here are up to `MAX_EXAMPLES` of
+    /// them, and whether there are more.
+    Ambiguous { examples: Vec<Decode>, more: bool },
+    /// No assignment fits all the samples.
+    Inconsistent,
 }
 
 impl Infer {
-    fn mark_possible(&mut self, inst: Inst, opcode: Reg) {
-        let ops = &mut self.possible[opcode];
-        if !ops.contains(&inst) {
-            ops.push(inst);
-        }
-    }
-
     pub fn new(sams: &[Sample]) -> Infer {
-        let mut possible = Vec::with_capacity(16);
-        let mut certain = Vec::with_capacity(16);
-        for _opcode in OPS {
-            possible.push(Vec::new());
-            certain.push(None);
-        }
-        let mut inf = Infer { possible, certain };
+        let mut possible: Vec<Vec<Opcode>> = OPS.map(|_| Opcode::all().to_vec()).collect();
         for s in sams {
-            // println!("{:?}", s);
             let pi = s.possible_inst();
-            let opcode = s.ops[0];
-            // println!("possible: {:?} => {:?}", opcode, pi);
-            for inst in pi {
-                inf.mark_possible(inst, opcode);
-            }
+            possible[s.ops[0]].retain(|inst| pi.contains(inst));
         }
-        inf
+        Infer { possible }
     }
 
-    /// Eliminate unambiguous encodings until we know all the opcodes.
+    /// Find every one-to-one assignment of instructions to opcode numbers
+    /// that's consistent with the samples.
     ///
-    /// This isn't guaranteed to terminate if we reach a point where there's
-    /// no single step without backtracking, but let's try it.
-    pub fn reduce(&mut self) -> Decode {
-        let mut solved_inst = BTreeMap::<Inst, Reg>::new();
-        while solved_inst.len() < 16 {
-            for (opcode, insts) in self.possible.iter().enumerate() {
-                // Look for opcodes that could decode to only one instruction
-                // whose value isn't already known.
-                let unresolved: Vec<Inst> = insts
-                    .iter()
-                    .filter(|inst| !solved_inst.contains_key(inst))
-                    .cloned()
-                    .collect();
-                if unresolved.len() == 1 {
-                    let inst = unresolved[0];
-                    println!("Found certainly {} === {:?}", opcode, inst);
-                    debug_assert!(self.certain[opcode].is_none());
-                    self.certain[opcode] = Some(inst);
-                    solved_inst.insert(inst, opcode);
+    /// This backtracks over the opcode with the fewest remaining choices,
+    /// and only descends into a choice if the rest can still be completed,
+    /// as checked by bipartite matching. So it never hits a dead end, and
+    /// the work is bounded by the number of solutions, which is capped at
+    /// one more than `MAX_EXAMPLES`.
+    pub fn solve(&self) -> Solution {
+        let mut assigned = vec![None; self.possible.len()];
+        let mut solutions = Vec::new();
+        if self.can_complete(&assigned) {
+            self.search(&mut assigned, &mut solutions);
+        }
+        match solutions.len() {
+            0 => Solution::Inconsistent,
+            1 => Solution::Unique(solutions.pop().unwrap()),
+            n => {
+                solutions.truncate(MAX_EXAMPLES);
+                Solution::Ambiguous {
+                    examples: solutions,
+                    more: n > MAX_EXAMPLES,
                 }
             }
         }
-        Decode {
-            op_ins: self.certain.iter().cloned().map(Option::unwrap).collect(),
+    }
+
+    fn search(&self, assigned: &mut Vec<Option<Opcode>>, solutions: &mut Vec<Decode>) {
+        if solutions.len() > MAX_EXAMPLES {
+            return;
+        }
+        let used: Vec<Opcode> = assigned.iter().flatten().cloned().collect();
+        let choices = |opcode: usize| -> Vec<Opcode> {
+            self.possible[opcode]
+                .iter()
+                .filter(|inst| !used.contains(inst))
+                .cloned()
+                .collect()
+        };
+        let next = (0..assigned.len())
+            .filter(|i| assigned[*i].is_none())
+            .min_by_key(|i| choices(*i).len());
+        let opcode = match next {
+            Some(opcode) => opcode,
+            None => {
+                solutions.push(Decode {
+                    op_ins: assigned.iter().cloned().map(Option::unwrap).collect(),
+                });
+                return;
+            }
+        };
+        for inst in choices(opcode) {
+            assigned[opcode] = Some(inst);
+            if self.can_complete(assigned) {
+                self.search(assigned, solutions);
+            }
+        }
+        assigned[opcode] = None;
+    }
+
+    /// True if the unassigned opcodes can all be given distinct unused
+    /// instructions, found by augmenting paths.
+    fn can_complete(&self, assigned: &[Option<Opcode>]) -> bool {
+        let mut owner: BTreeMap<Opcode, usize> = BTreeMap::new();
+        for (opcode, inst) in assigned.iter().enumerate() {
+            if let Some(inst) = inst {
+                owner.insert(*inst, opcode);
+            }
         }
+        let fixed: Vec<Opcode> = owner.keys().cloned().collect();
+        (0..assigned.len())
+            .filter(|i| assigned[*i].is_none())
+            .all(|opcode| self.augment(opcode, &fixed, &mut owner, &mut Vec::new()))
+    }
+
+    fn augment(
+        &self,
+        opcode: usize,
+        fixed: &[Opcode],
+        owner: &mut BTreeMap<Opcode, usize>,
+        visited: &mut Vec<Opcode>,
+    ) -> bool {
+        for &inst in &self.possible[opcode] {
+            if fixed.contains(&inst) || visited.contains(&inst) {
+                continue;
+            }
+            visited.push(inst);
+            let free = match owner.get(&inst) {
+                None => true,
+                Some(&other) => self.augment(other, fixed, owner, visited),
+            };
+            if free {
+                owner.insert(inst, opcode);
+                return true;
+            }
+        }
+        false
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Decode {
     // Opcode -> Instruction mapping
-    op_ins: Vec<Inst>,
+    op_ins: Vec<Opcode>,
 }
 
 pub struct Program {
@@ -238,9 +264,9 @@ impl Program {
     pub fn eval(&self, decode: &Decode) {
         let mut r = [0; 4];
         for p in self.asm.iter() {
-            let inst = decode.op_ins[p[0]];
-            println!("{:?} {:?}", inst, &p[1..]);
-            r = inst.apply(&r, &p);
+            let inst = decode_inst(decode.op_ins[p[0]], p);
+            println!("{}", inst);
+            inst.apply(&mut r);
             println!(" => {:?}", r);
         }
     }
@@ -259,8 +285,16 @@ pub fn main() {
     let samples = splits.next().unwrap();
     let ls: Vec<String> = samples.lines().map(|l| l.to_string()).collect();
     let sams = Sample::parse_samples(&ls);
-    let mut infer = Infer::new(&sams);
-    let decode = infer.reduce();
+    let decode = match Infer::new(&sams).solve() {
+        Solution::Unique(decode) => decode,
+        Solution::Ambiguous { examples, more } => panic!(
+            "{}{} possible decodings, including: {:?}",
+            examples.len(),
+            if more { "+" } else { "" },
+            examples
+        ),
+        Solution::Inconsistent => panic!("no decoding fits all the samples"),
+    };
     let prog = Program::parse(splits.next().unwrap());
     prog.eval(&decode);
 }
@@ -307,21 +341,25 @@ mod test {
     }
 
     #[test]
-    fn simple_inst() {
+    fn decode_sample_inst() {
         // muli (multiply immediate) stores into register C the result of
         // multiplying register A and value B.
         // r0 = r2 * 10
-        assert_eq!(Muli.apply(&[1, 2, 3, 4], &[42, 2, 10, 0]), [30, 2, 3, 4]);
+        let mut r = [1, 2, 3, 4];
+        decode_inst(Opcode::Muli, &[42, 2, 10, 0]).apply(&mut r);
+        assert_eq!(r, [30, 2, 3, 4]);
+    }
 
-        // r0 = (r2 == r3)
-        assert_eq!(
-            Eqrr.apply(&[10, 20, 30, 40], &[42, 2, 3, 0]),
-            [0, 20, 30, 40]
-        );
-        assert_eq!(
-            Eqrr.apply(&[10, 20, 30, 30], &[42, 2, 3, 0]),
-            [1, 20, 30, 30]
-        );
+    #[test]
+    fn impossible_if_faulting() {
+        // Reading register 7 would fault, rather than panic.
+        let ss = Sample::parse_samples(&[
+            "Before: [3, 2, 1, 1]",
+            "9 7 1 2",
+            "After:  [3, 2, 7, 1]",
+            "",
+        ]);
+        assert_eq!(ss[0].possible_inst(), vec![Opcode::Seti]);
     }
 
     #[test]
@@ -332,6 +370,89 @@ mod test {
             "After:  [3, 2, 2, 1]",
             "",
         ]);
-        assert_eq!(ss[0].possible_inst(), vec![Mulr, Addi, Seti]);
+        assert_eq!(
+            ss[0].possible_inst(),
+            vec![Opcode::Mulr, Opcode::Addi, Opcode::Seti]
+        );
+    }
+
+    /// An Infer where opcode number i can only be instruction i, except as
+    /// overridden.
+    fn infer_with(overrides: &[(usize, &[Opcode])]) -> Infer {
+        let mut possible: Vec<Vec<Opcode>> = Opcode::all().iter().map(|op| vec![*op]).collect();
+        for (opcode, insts) in overrides {
+            possible[*opcode] = insts.to_vec();
+        }
+        Infer { possible }
+    }
+
+    #[test]
+    fn solve_input() {
+        let s = include_str!("../../input/input16.txt");
+        let ls: Vec<&str> = s.split("\n\n\n").next().unwrap().lines().collect();
+        let infer = Infer::new(&Sample::parse_samples(&ls));
+        match infer.solve() {
+            Solution::Unique(decode) => assert_eq!(decode.op_ins.len(), 16),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn solve_ambiguous() {
+        let all = Opcode::all();
+        let pair = [all[0], all[1]];
+        match infer_with(&[(0, &pair), (1, &pair)]).solve() {
+            Solution::Ambiguous {
+                examples: decodes,
+                more: false,
+            } => {
+                assert_eq!(decodes.len(), 2);
+                assert_eq!(&decodes[0].op_ins[..2], &[all[0], all[1]]);
+                assert_eq!(&decodes[1].op_ins[..2], &[all[1], all[0]]);
+                assert_eq!(decodes[0].op_ins[2..], decodes[1].op_ins[2..]);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn solve_stalled_elimination() {
+        // No opcode number is down to one choice, which used to loop
+        // forever.
+        let all = Opcode::all();
+        let four = &all[..4];
+        let infer = infer_with(&[(0, four), (1, four), (2, four), (3, four)]);
+        match infer.solve() {
+            Solution::Ambiguous {
+                examples: decodes,
+                more: false,
+            } => assert_eq!(decodes.len(), 24),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn solve_anything_goes() {
+        // 16! assignments, of which only a few are listed.
+        let infer = infer_with(&OPS.map(|i| (i, Opcode::all())).collect::<Vec<_>>());
+        match infer.solve() {
+            Solution::Ambiguous {
+                examples,
+                more: true,
+            } => assert_eq!(examples.len(), MAX_EXAMPLES),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn solve_inconsistent() {
+        let all = Opcode::all();
+        // Two opcode numbers that can only be the same instruction.
+        assert_eq!(
+            infer_with(&[(0, &[all[1]])]).solve(),
+            Solution::Inconsistent
+        );
+        // An opcode number no instruction fits.
+        assert_eq!(infer_with(&[(3, &[])]).solve(), Solution::Inconsistent);
     }
 }
@@ -18,11 +18,11 @@ use std::cmp::{max, min};
 
 use regex::Regex;
 
-use aoc2018::Matrix;
-use aoc2018::{point, Point};
+use aoc2018::Glyph;
+use aoc2018::{ipoint, Grid, IPoint};
 
-// Read the input lines and draw into a matrix. Maybe pre-scan to work out the
-// maximum dimensions.
+// Read the input lines and draw into a grid, which grows to fit wherever the
+// water flows.
 //
 // Iterate from each "drip" until reaching a stable state.
 //
@@ -56,8 +56,20 @@ impl Thing {
             Sand | Damp => false,
         }
     }
+}
+
+impl Glyph for Thing {
+    fn from_char(c: char) -> Option<Thing> {
+        match c {
+            '#' => Some(Clay),
+            '.' => Some(Sand),
+            '|' => Some(Damp),
+            '~' => Some(Water),
+            _ => None,
+        }
+    }
 
-    pub fn to_char(self) -> char {
+    fn to_char(&self) -> char {
         match self {
             Clay => '#',
             Sand => '.',
@@ -69,8 +81,8 @@ impl Thing {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum Line {
-    Vertical { x: usize, y1: usize, y2: usize },
-    Horizontal { y: usize, x1: usize, x2: usize },
+    Vertical { x: isize, y1: isize, y2: isize },
+    Horizontal { y: isize, x1: isize, x2: isize },
 }
 
 impl Line {
@@ -95,7 +107,7 @@ impl Line {
         v
     }
 
-    fn x_range(ls: &[Line]) -> (usize, usize) {
+    fn x_range(ls: &[Line]) -> (isize, isize) {
         let xmin = ls
             .iter()
             .map(|l| match l {
@@ -115,7 +127,7 @@ impl Line {
         (*xmin, *xmax)
     }
 
-    fn y_range(ls: &[Line]) -> (usize, usize) {
+    fn y_range(ls: &[Line]) -> (isize, isize) {
         let ymin = ls
             .iter()
             .map(|l| match l {
@@ -137,29 +149,29 @@ impl Line {
 }
 
 pub struct Map {
-    m: Matrix<Thing>,
-    drip: Vec<Point>,
-    x_min: usize,
-    x_max: usize,
-    y_min: usize,
-    y_max: usize,
+    m: Grid<Thing>,
+    drip: Vec<IPoint>,
+    x_min: isize,
+    x_max: isize,
+    y_min: isize,
+    y_max: isize,
 }
 
 impl Map {
     fn from_lines(ls: &[Line]) -> Map {
         let (x_min, x_max) = Line::x_range(ls);
         let (y_min, y_max) = Line::y_range(ls);
-        let mut m = Matrix::new(x_max + 2, y_max + 2, Sand);
+        let mut m = Grid::new(Sand);
         for l in ls.iter() {
             match *l {
                 Line::Vertical { x, y1, y2 } => {
                     for y in y1..=y2 {
-                        m[point(x, y)] = Clay;
+                        m[ipoint(x, y)] = Clay;
                     }
                 }
                 Line::Horizontal { x1, x2, y } => {
                     for x in x1..=x2 {
-                        m[point(x, y)] = Clay;
+                        m[ipoint(x, y)] = Clay;
                     }
                 }
             }
@@ -169,7 +181,7 @@ impl Map {
             x_min, x_max, y_min, y_max
         );
         // Skip from (500, 0) down to the first point on the map.
-        let drip1 = point(500, y_min);
+        let drip1 = ipoint(500, y_min);
         let mut map = Map {
             m,
             drip: vec![],
@@ -182,7 +194,7 @@ impl Map {
         map
     }
 
-    fn check_point(&self, p: Point) {
+    fn check_point(&self, p: IPoint) {
         // NB: Any x-coordinate is valid, however it can only overflow one column to the left or right.
         assert!(
             p.x <= (self.x_max + 1) && self.y_min <= p.y && p.y <= self.y_max,
@@ -217,7 +229,7 @@ impl Map {
         }
     }
 
-    fn add_drip(&mut self, p: Point) {
+    fn add_drip(&mut self, p: IPoint) {
         match self.m[p] {
             Clay => panic!("can't drip through clay at {:?}", p),
             Sand => {
@@ -241,7 +253,7 @@ impl Map {
     /// leak out. If it's enclosed on both sides and below, fill this with water, otherwise
     /// with damp sand. And, if it can leak from either or both sides, create a new drip from
     /// there.
-    fn spread(&mut self, drp: Point) {
+    fn spread(&mut self, drp: IPoint) {
         println!("water spreads from {:?}", drp);
         let mut pl = drp;
         let mut leak_left = true;
@@ -293,32 +305,27 @@ impl Map {
         }
     }
 
-    fn fill(&mut self, th: Thing, p1: Point, p2: Point) {
+    fn fill(&mut self, th: Thing, p1: IPoint, p2: IPoint) {
         assert_eq!(p1.y, p2.y);
         for x in min(p1.x, p2.x)..=max(p1.x, p2.x) {
-            let p = point(x, p1.y);
+            let p = ipoint(x, p1.y);
             assert!(self.m[p] != Clay);
             self.m[p] = th;
         }
     }
 
     fn count_wet(&self) -> usize {
-        self.m.values().filter(|t| t.is_wet()).count()
+        self.m.iter_points().filter(|p| self.m[*p].is_wet()).count()
     }
 
     fn count_water(&self) -> usize {
-        self.m.values().filter(|t| **t == Water).count()
+        self.m.iter_points().filter(|p| self.m[*p] == Water).count()
     }
 
+    /// Draw everything from the top-left of the clay or water to the
+    /// bottom-right.
     fn render(&self) -> String {
-        let mut s = String::new();
-        for y in 0..=self.y_max {
-            for x in (self.x_min - 1)..=(self.x_max + 1) {
-                s.push(self.m[point(x, y)].to_char());
-            }
-            s.push('\n')
-        }
-        s
+        self.m.to_matrix().render_ascii()
     }
 }
 
@@ -336,7 +343,7 @@ pub fn solve_main_input() -> (usize, usize) {
 
 /// Solve the puzzle in the given representation
 pub fn solve_str(s: &str) -> (usize, usize) {
-    let mut map = Map::from_lines(&Line::parse_lines(&s));
+    let mut map = Map::from_lines(&Line::parse_lines(s));
     // write!(File::create("aoc17before.txt").unwrap(), "{}", map.render()).unwrap();
     map.run();
     // write!(File::create("aoc17after.txt").unwrap(), "{}", map.render()).unwrap();
@@ -379,8 +386,8 @@ y=13, x=498..504
         assert_eq!(Line::y_range(&ls), (1, 13));
 
         let mut map = Map::from_lines(&ls);
-        assert_eq!(map.m[point(495, 2)], Clay);
-        assert_eq!(map.m[point(495, 7)], Clay);
+        assert_eq!(map.m[ipoint(495, 2)], Clay);
+        assert_eq!(map.m[ipoint(495, 7)], Clay);
 
         map.run();
         assert_eq!(57, map.count_wet());
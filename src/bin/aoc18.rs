@@ -24,17 +24,17 @@ enum Thing {
 }
 use self::Thing::*;
 
-impl Thing {
-    pub fn from_char(c: char) -> Thing {
+impl Glyph for Thing {
+    fn from_char(c: char) -> Option<Thing> {
         match c {
-            '.' => Open,
-            '|' => Trees,
-            '#' => Lumberyard,
-            other => panic!("unexpected character {:?}", other),
+            '.' => Some(Open),
+            '|' => Some(Trees),
+            '#' => Some(Lumberyard),
+            _ => None,
         }
     }
 
-    pub fn to_char(self) -> char {
+    fn to_char(&self) -> char {
         match self {
             Open => '.',
             Trees => '|',
@@ -45,63 +45,53 @@ impl Thing {
 
 #[derive(Clone, Eq, PartialEq)]
 struct Map {
-    m: Matrix<Thing>,
+    life: Automaton<Thing>,
+}
+
+fn rule(th: &Thing, ns: &[Thing]) -> Thing {
+    let count = |x| ns.iter().filter(|n| **n == x).count();
+    match th {
+        Open => {
+            if count(Trees) >= 3 {
+                Trees
+            } else {
+                Open
+            }
+        }
+        Trees => {
+            if count(Lumberyard) >= 3 {
+                Lumberyard
+            } else {
+                Trees
+            }
+        }
+        Lumberyard => {
+            if ns.contains(&Lumberyard) && ns.contains(&Trees) {
+                Lumberyard
+            } else {
+                Open
+            }
+        }
+    }
 }
 
 impl Map {
     pub fn parse(s: &str) -> Map {
-        let mut mb = Matrix::from_rows();
-        for l in s.lines() {
-            let v: Vec<Thing> = l.chars().map(Thing::from_char).collect();
-            mb.add_row(&v);
+        Map {
+            life: Automaton::new(Matrix::parse_ascii(s).unwrap_or_else(|e| panic!("{}", e))),
         }
-        Map { m: mb.finish() }
     }
 
     pub fn render(&self) -> String {
-        let mut s = String::with_capacity(self.m.height() * (self.m.width() + 1));
-        for y in 0..self.m.height() {
-            for x in 0..self.m.width() {
-                s.push(self.m[point(x, y)].to_char())
-            }
-            s.push('\n')
-        }
-        s
+        self.life.matrix().render_ascii()
     }
 
-    pub fn step(&self) -> Map {
-        let mut newm = Matrix::new(self.m.width(), self.m.height(), Open);
-        for p in self.m.iter_points() {
-            let ns = self.m.neighbor8_values(p);
-            newm[p] = match self.m[p] {
-                Open => {
-                    if ns.into_iter().filter(|n| *n == Trees).count() >= 3 {
-                        Trees
-                    } else {
-                        Open
-                    }
-                }
-                Trees => {
-                    if ns.into_iter().filter(|n| *n == Lumberyard).count() >= 3 {
-                        Lumberyard
-                    } else {
-                        Trees
-                    }
-                }
-                Lumberyard => {
-                    if ns.contains(&Lumberyard) && ns.contains(&Trees) {
-                        Lumberyard
-                    } else {
-                        Open
-                    }
-                }
-            }
-        }
-        Map { m: newm }
+    pub fn step(&mut self) {
+        self.life.step(rule)
     }
 
     pub fn count(&self, th: Thing) -> usize {
-        self.m.values().filter(|t| **t == th).count()
+        self.life.matrix().count(|t| *t == th)
     }
 
     pub fn resource_value(&self) -> usize {
@@ -123,7 +113,7 @@ fn load_input() -> Map {
 fn solve18a() -> usize {
     let mut m = load_input();
     for _i in 0..10 {
-        m = m.step();
+        m.step();
     }
     m.resource_value()
 }
@@ -139,17 +129,17 @@ fn solve18b() -> usize {
     const STAB: u64 = 1000;
     for _i in 0..STAB {
         // Run a bit to let it stabilize
-        m = m.step();
+        m.step();
     }
     let mstab = m.clone();
     let mut j = STAB;
     let cycle = loop {
-        m = m.step();
+        m.step();
         // println!("{}\n{}", j, m.render());
         println!("{}", j);
         if m == mstab {
             println!("Found cycle: gen {} == gen {}", j, STAB);
-            break (j - STAB) as u64;
+            break j - STAB;
         }
         j += 1;
     };
@@ -164,7 +154,6 @@ fn solve18b() -> usize {
     );
     for _ in 0..more {
         m.step();
-        j += 1;
     }
     m.resource_value()
 }
@@ -180,7 +169,7 @@ mod test {
 
     #[test]
     fn example() {
-        let m = Map::parse(
+        let mut m = Map::parse(
             "\
 .#.#...|#.
 .....#|##|
@@ -195,11 +184,11 @@ mod test {
 ",
         );
         println!("{}", m.render());
-        let mut m = m.step();
+        m.step();
         println!("{}", m.render());
 
         for _i in 2..=10 {
-            m = m.step();
+            m.step();
             println!("{}", m.render());
         }
         println!("resource value = {}", m.resource_value());
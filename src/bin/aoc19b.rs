@@ -12,14 +12,37 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fs::File;
+use std::io::prelude::*;
+use std::str::FromStr;
+
+use aoc2018::elfcode::{Program, Reg};
+
+/// Run the program starting with register 0 set to 1.
+///
+/// Interpreted directly, this sums the factors of 10551425 by trying every
+/// pair of candidates, which would take forever; the optimizer recognizes
+/// the inner loop and computes it directly.
+pub fn solve() -> Reg {
+    let mut s = String::new();
+    File::open("input/input19.txt")
+        .unwrap()
+        .read_to_string(&mut s)
+        .unwrap();
+    let mut prog = Program::from_str(&s).unwrap();
+    prog.reg[0] = 1;
+    prog.optimize();
+    prog.eval()
+}
+
 fn main() {
-    let r3 = 10551425;
-    let mut r0 = 0;
-    for i in 1..=r3 {
-        if (r3 / i) * i == r3 {
-            println!("factor {}", i);
-            r0 += i;
-        }
+    println!("r0 = {}", solve());
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn overall_result() {
+        assert_eq!(super::solve(), 13083798);
     }
-    println!("r0 = {}", r0);
 }
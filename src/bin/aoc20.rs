@@ -16,50 +16,11 @@
 
 //! https://adventofcode.com/2018/day/20
 
-use std::collections::BTreeSet;
-use std::fmt;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::File;
 use std::io::prelude::*;
 
-type Coord = i32;
-#[derive(PartialEq, Eq, Copy, Clone, PartialOrd, Ord)]
-struct Point {
-    x: Coord,
-    y: Coord,
-}
-
-impl Point {
-    #[allow(unused)]
-    pub fn update(&mut self, dir: Dir) {
-        *self = self.step(dir);
-    }
-
-    pub fn step(self, dir: Dir) -> Point {
-        let mut n = self;
-        match dir {
-            Dir::N => n.y -= 1,
-            Dir::S => n.y += 1,
-            Dir::E => n.x += 1,
-            Dir::W => n.x -= 1,
-        };
-        n
-    }
-
-    pub fn origin() -> Point {
-        Point { x: 0, y: 0 }
-    }
-}
-
-#[allow(unused)]
-fn pt(x: Coord, y: Coord) -> Point {
-    Point { x, y }
-}
-
-impl fmt::Debug for Point {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "({}, {})", self.x, self.y)
-    }
-}
+use aoc2018::{all_distances, ipoint, Grid, IPoint};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 enum Dir {
@@ -92,6 +53,23 @@ impl Dir {
             Dir::W => 'W',
         }
     }
+
+    /// The room next to `p` in this direction.
+    fn step(self, p: IPoint) -> IPoint {
+        match self {
+            Dir::N => p.up(),
+            Dir::S => p.down(),
+            Dir::E => p.right(),
+            Dir::W => p.left(),
+        }
+    }
+}
+
+/// The doors leading north and east out of one room.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct Doors {
+    n: bool,
+    e: bool,
 }
 
 /// Map of rooms that have been visited.
@@ -103,107 +81,80 @@ impl Dir {
 ///
 /// Note that we can't pass directly between neighboring rooms unless there is a
 /// door.
-#[derive(Default, Debug)]
+#[derive(Debug)]
 struct Map {
-    /// For each entry, there's a door to the north.
-    n_doors: BTreeSet<Point>,
-    /// For each entry there's a door to the east.
-    e_doors: BTreeSet<Point>,
+    /// The doors to the north and east of each room; the ones to the south
+    /// and west are recorded in the neighboring rooms.
+    doors: Grid<Doors>,
+}
+
+impl Default for Map {
+    fn default() -> Map {
+        Map {
+            doors: Grid::new(Doors::default()),
+        }
+    }
 }
 
 impl Map {
     /// Note that you can move from p in direction d (and so also in the
     /// opposite direction.)
-    fn record_move(&mut self, p: Point, d: Dir) {
+    fn record_move(&mut self, p: IPoint, d: Dir) {
         match d {
-            Dir::N => self.n_doors.insert(p),
-            Dir::E => self.e_doors.insert(p),
-            Dir::S => self.n_doors.insert(p.step(d)),
-            Dir::W => self.e_doors.insert(p.step(d)),
+            Dir::N => self.doors[p].n = true,
+            Dir::E => self.doors[p].e = true,
+            Dir::S => self.doors[d.step(p)].n = true,
+            Dir::W => self.doors[d.step(p)].e = true,
         };
     }
 
     /// Return all rooms reachable through a door from p.
-    fn neighbors(&self, p: Point) -> Vec<Point> {
+    fn neighbors(&self, p: IPoint) -> Vec<IPoint> {
         let mut v = Vec::with_capacity(4);
-        if self.n_doors.contains(&p) {
-            v.push(p.step(Dir::N))
+        if self.doors[p].n {
+            v.push(p.up())
         }
-        if self.e_doors.contains(&p) {
-            v.push(p.step(Dir::E))
+        if self.doors[p].e {
+            v.push(p.right())
         }
-        let p2 = p.step(Dir::W);
-        if self.e_doors.contains(&p2) {
-            v.push(p2)
+        if self.doors[p.left()].e {
+            v.push(p.left())
         }
-        let p2 = p.step(Dir::S);
-        if self.n_doors.contains(&p2) {
-            v.push(p2)
+        if self.doors[p.down()].n {
+            v.push(p.down())
         }
         v
     }
 
+    /// All the rooms with a door out in direction `d`, which must be N or E,
+    /// in reading order.
+    #[cfg(test)]
+    fn doors(&self, d: Dir) -> Vec<IPoint> {
+        self.doors
+            .iter_points()
+            .filter(|p| match d {
+                Dir::N => self.doors[*p].n,
+                Dir::E => self.doors[*p].e,
+                _ => panic!("doors are recorded to the N and E, not {:?}", d),
+            })
+            .collect()
+    }
+
+    /// The number of doors on the shortest path to every room.
+    fn distances(&self) -> BTreeMap<IPoint, isize> {
+        all_distances(ipoint(0, 0), &mut |p| {
+            self.neighbors(p).into_iter().map(|n| (n, 1)).collect()
+        })
+    }
+
     /// Find the number of rooms at least 1000 doors from the origin.
     fn far_rooms(&self) -> usize {
-        // Successively visit all neighboring rooms at distance `depth`, that have not yet been
-        // seen, until we have no more to visit.
-        let mut depth = 0;
-        let mut seen = BTreeSet::new();
-        let mut next = BTreeSet::new();
-        let mut far_count = 0;
-        next.insert(Point::origin());
-        loop {
-            // println!("depth {}, seen={:?}, next={:?}", depth, seen, next);
-            let mut new_rooms = BTreeSet::new();
-            for r in next {
-                assert!(seen.insert(r));
-                if depth >= 1000 {
-                    far_count += 1;
-                }
-                for n in self.neighbors(r) {
-                    if !seen.contains(&n) {
-                        // println!("  visit {:?} from {:?}", n, r);
-                        new_rooms.insert(n);
-                    }
-                }
-            }
-            next = new_rooms;
-            if next.is_empty() {
-                break;
-            } else {
-                depth += 1;
-            }
-        }
-        far_count
+        self.distances().values().filter(|d| **d >= 1000).count()
     }
 
     /// Find the longest shortest-path from the origin.
     fn furthest(&self) -> usize {
-        // Successively visit all neighboring rooms at distance `depth`, that have not yet been
-        // seen, until we have no more to visit.
-        let mut depth = 0;
-        let mut seen = BTreeSet::new();
-        let mut next = BTreeSet::new();
-        next.insert(Point::origin());
-        loop {
-            // println!("depth {}, seen={:?}, next={:?}", depth, seen, next);
-            let mut new_rooms = BTreeSet::new();
-            for r in next {
-                assert!(seen.insert(r));
-                for n in self.neighbors(r) {
-                    if !seen.contains(&n) {
-                        // println!("  visit {:?} from {:?}", n, r);
-                        new_rooms.insert(n);
-                    }
-                }
-            }
-            next = new_rooms;
-            if next.is_empty() {
-                return depth;
-            } else {
-                depth += 1;
-            }
-        }
+        *self.distances().values().max().unwrap() as usize
     }
 }
 
@@ -213,13 +164,13 @@ impl Map {
 struct GroupState {
     /// The positions of active turtles at the point of entering this group.
     /// Each alternate branch will start from here.
-    sps: BTreeSet<Point>,
+    sps: BTreeSet<IPoint>,
 
     /// The total accumulated positions of active turtles at the end of
     /// evalutaing each branch. This doesn't include everything they might
     /// have moved through during the branch. But, if sub-branches multiply
     /// turtles, they'll all be here.
-    eps: BTreeSet<Point>,
+    eps: BTreeSet<IPoint>,
 }
 
 fn expand(r: &str) -> Map {
@@ -242,7 +193,7 @@ fn expand(r: &str) -> Map {
 
     // Currently-live turtle positions, for the current branch.
     let mut turs = BTreeSet::new();
-    turs.insert(Point::origin());
+    turs.insert(ipoint(0, 0));
 
     for c in r.chars() {
         match c {
@@ -251,7 +202,7 @@ fn expand(r: &str) -> Map {
                 let mut newturs = BTreeSet::new();
                 for t in turs {
                     map.record_move(t, dir);
-                    newturs.insert(t.step(dir));
+                    newturs.insert(dir.step(t));
                 }
                 turs = newturs;
             }
@@ -307,7 +258,7 @@ fn load_input() -> String {
         .read_to_string(&mut s)
         .unwrap();
     s = s.trim_end().to_string();
-    assert!(s.ends_with('$'), s);
+    assert!(s.ends_with('$'), "{}", s);
     assert!(s.starts_with('^'));
     s[1..(s.len() - 1)].to_string()
 }
@@ -331,8 +282,8 @@ pub fn main() {
 
 #[cfg(test)]
 mod test {
-    use super::pt;
-    use super::Point;
+    use super::Dir;
+    use aoc2018::ipoint as pt;
 
     #[test]
     fn test_load_input() {
@@ -365,29 +316,27 @@ mod test {
         // #.|.|.|.#
         // #########
         //
-        // They're all sorted by x first then y, and y runs downwards. X is the origin in the
+        // They're all in reading order, and y runs downwards. X is the origin in the
         // diagram.
         let map = super::expand("ENWWW(NEEE|SSE(EE|N))");
-        let e_doors: Vec<Point> = map.e_doors.iter().cloned().collect();
         assert_eq!(
-            e_doors,
+            map.doors(Dir::E),
             vec![
                 pt(-2, -2),
-                pt(-2, -1),
-                pt(-2, 1),
                 pt(-1, -2),
-                pt(-1, -1),
-                pt(-1, 1),
                 pt(0, -2),
+                pt(-2, -1),
+                pt(-1, -1),
                 pt(0, -1),
                 pt(0, 0),
+                pt(-2, 1),
+                pt(-1, 1),
                 pt(0, 1)
             ]
         );
-        let n_doors: Vec<Point> = map.n_doors.iter().cloned().collect();
         assert_eq!(
-            n_doors,
-            vec![pt(-2, -1), pt(-2, 0), pt(-2, 1), pt(-1, 1), pt(1, 0),]
+            map.doors(Dir::N),
+            vec![pt(-2, -1), pt(-2, 0), pt(1, 0), pt(-2, 1), pt(-1, 1)]
         );
         assert_eq!(map.furthest(), 10);
     }
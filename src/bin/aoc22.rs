@@ -18,12 +18,12 @@
 
 use std::collections::BTreeMap;
 
-use aoc2018::shortest_distance;
+use aoc2018::{astar_path_with, DenseTable, DistanceTable, Limits, Route};
 use aoc2018::{point, Point};
 
 type Erosion = usize;
 
-#[derive(Clone, Copy, Debug, PartialOrd, Ord, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
 enum Tool {
     Climbing,
     Torch,
@@ -60,7 +60,7 @@ struct Map {
 }
 
 /// Combination of a location, and a tool.
-#[derive(Debug, Clone, Copy, PartialOrd, Ord, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialOrd, Ord, Eq, PartialEq, Hash)]
 struct State {
     p: Point,
     t: Tool,
@@ -149,9 +149,8 @@ impl Map {
         r
     }
 
-    /// Find the target, walking the shortest path, taking account of tool
-    /// transitions. Returns the time taken.
-    fn rescue(&mut self) -> isize {
+    /// Start at the mouth and end at the target, both holding the torch.
+    fn endpoints(&self) -> (State, State) {
         let start = State {
             p: point(0, 0),
             t: Torch,
@@ -160,22 +159,76 @@ impl Map {
             p: self.target,
             t: Torch,
         };
-        // shortest_distance(start, dest, &mut |state| self.neighbors(state))
-        shortest_distance(start, dest, &mut |state| self.neighbors(state))
+        (start, dest)
     }
+
+    /// Find the target, walking the shortest path, taking account of tool
+    /// transitions. Returns the time taken.
+    fn rescue(&mut self) -> isize {
+        self.rescue_route().distance
+    }
+
+    /// Find the shortest path to the target, like `rescue`, and return the
+    /// whole route.
+    ///
+    /// The search is guided by the Manhattan distance to the target, plus
+    /// the time to switch to the torch if we're not holding it: there's no
+    /// way to skip either.
+    fn rescue_route(&mut self) -> Route<State> {
+        let target = self.target;
+        self.route_with(
+            |st| {
+                let dx = (st.p.x as isize - target.x as isize).abs();
+                let dy = (st.p.y as isize - target.y as isize).abs();
+                dx + dy + if st.t == Torch { 0 } else { 7 }
+            },
+            &mut DenseTable::new(state_index),
+        )
+    }
+
+    /// Find the shortest path to the target, using `heuristic` to estimate
+    /// the remaining time from each state, and keeping distances in `best`.
+    fn route_with<T: DistanceTable<State>>(
+        &mut self,
+        heuristic: impl FnMut(State) -> isize,
+        best: &mut T,
+    ) -> Route<State> {
+        let (start, dest) = self.endpoints();
+        astar_path_with(
+            start,
+            dest,
+            &mut |state| self.neighbors(state),
+            heuristic,
+            Limits::default(),
+            best,
+        )
+        .expect("no route to the target")
+    }
+}
+
+/// Number states compactly, for a `DenseTable`.
+///
+/// Points are numbered in square shells around the origin, so that those
+/// within `k` of it in both directions all come before `(k + 1)^2`, however
+/// far the search wanders in either direction. Each point has a number for
+/// each tool.
+fn state_index(st: &State) -> usize {
+    let (x, y) = (st.p.x, st.p.y);
+    let k = x.max(y);
+    (k * k + k + x - y) * 3 + st.t as usize
 }
 
 /// True if tool `t` is allowed in on ground `g`.
 fn legal(t: Tool, g: Ground) -> bool {
-    match (g, t) {
+    matches!(
+        (g, t),
         (Rocky, Climbing)
-        | (Rocky, Torch)
-        | (Wet, Climbing)
-        | (Wet, Neither)
-        | (Narrow, Torch)
-        | (Narrow, Neither) => true,
-        _ => false,
-    }
+            | (Rocky, Torch)
+            | (Wet, Climbing)
+            | (Wet, Neither)
+            | (Narrow, Torch)
+            | (Narrow, Neither)
+    )
 }
 
 pub fn solve() -> (usize, isize) {
@@ -191,8 +244,7 @@ pub fn main() {
 
 #[cfg(test)]
 mod test {
-    use super::Map;
-    use aoc2018::point;
+    use super::*;
 
     #[test]
     fn build_map() {
@@ -216,4 +268,103 @@ mod test {
         let mut map = Map::new(510, point(10, 10));
         assert_eq!(map.rescue(), 45);
     }
+
+    /// Check the routes A* and Dijkstra find through the example cave.
+    ///
+    /// They may break ties differently, so this doesn't reproduce the walk
+    /// in the puzzle text: it only checks the total time, the endpoints, and
+    /// that each step is a legal move or tool switch with the right cost.
+    #[test]
+    fn example_route() {
+        let mut map = Map::new(510, point(10, 10));
+        for route in &[
+            map.rescue_route(),
+            map.route_with(|_| 0, &mut BTreeMap::new()),
+        ] {
+            assert_eq!(route.distance, 45);
+            assert_eq!(route.costs.iter().sum::<isize>(), 45);
+            assert_eq!(route.states.first(), Some(&map.endpoints().0));
+            assert_eq!(route.states.last(), Some(&map.endpoints().1));
+            assert_eq!(route.costs.len() + 1, route.states.len());
+            for (w, cost) in route.states.windows(2).zip(&route.costs) {
+                let (a, b) = (w[0], w[1]);
+                assert!(legal(b.t, map.ground_at(b.p)), "{:?} is illegal", b);
+                let expected = if a.t != b.t { 7 } else { 1 };
+                assert_eq!(*cost, expected, "{:?} to {:?}", a, b);
+                assert!(
+                    map.neighbors(a).contains(&(b, *cost)),
+                    "can't go from {:?} to {:?} in {}",
+                    a,
+                    b,
+                    cost
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn astar_matches_dijkstra() {
+        let mut map = Map::new(5616, point(10, 785));
+        let dijkstra = map.route_with(|_| 0, &mut BTreeMap::new());
+        let astar = map.rescue_route();
+        assert_eq!(astar.distance, 1070);
+        assert_eq!(astar.distance, dijkstra.distance);
+        assert_eq!(dijkstra.expanded, 919037);
+        assert_eq!(astar.expanded, 162513);
+    }
+
+    #[test]
+    fn state_index_is_unique() {
+        let mut seen = std::collections::BTreeSet::new();
+        for x in 0..30 {
+            for y in 0..30 {
+                for t in &[Torch, Climbing, Neither] {
+                    let i = state_index(&State {
+                        p: point(x, y),
+                        t: *t,
+                    });
+                    assert!(seen.insert(i), "{} repeated", i);
+                    assert!(i < 30 * 30 * 3);
+                }
+            }
+        }
+    }
+
+    /// Compare the speed of each kind of distance table, searching the real
+    /// cave.
+    ///
+    /// Run with `cargo test --release --bin aoc22 -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_tables() {
+        use std::collections::HashMap;
+        use std::time::Instant;
+
+        fn time<T: DistanceTable<State>>(map: &mut Map, name: &str, mut best: T) {
+            let start = Instant::now();
+            let route = map.route_with(|_| 0, &mut best);
+            assert_eq!(route.distance, 1070);
+            println!(
+                "{:>10}: {:>8.3}s, {} expanded",
+                name,
+                start.elapsed().as_secs_f64(),
+                route.expanded
+            );
+        }
+
+        let mut map = Map::new(5616, point(10, 785));
+        // Fill in the cave's erosion levels first, so that isn't counted.
+        map.route_with(|_| 0, &mut HashMap::new());
+        time(&mut map, "BTreeMap", BTreeMap::new());
+        time(&mut map, "HashMap", HashMap::new());
+        time(&mut map, "Dense", DenseTable::new(state_index));
+        let start = Instant::now();
+        let route = map.rescue_route();
+        println!(
+            "{:>10}: {:>8.3}s, {} expanded",
+            "Dense A*",
+            start.elapsed().as_secs_f64(),
+            route.expanded
+        );
+    }
 }
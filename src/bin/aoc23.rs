@@ -104,8 +104,8 @@ fn strongest(bs: &[Bot]) -> Bot {
 
 /// Return the number of bots in range of the strongest bot (including itself.)
 fn count_in_range(bs: &[Bot]) -> usize {
-    let stz = strongest(&bs).zone();
-    bs.iter().filter(|b| stz.contains(&b)).count()
+    let stz = strongest(bs).zone();
+    bs.iter().filter(|b| stz.contains(b)).count()
 }
 
 /// Load bots from input file.
@@ -277,7 +277,7 @@ fn attack_repeatedly(gs: &mut [Group]) -> Option<(Side, usize)> {
         if !attack_round(gs) {
             return None;
         }
-        if let Some((s, v)) = victory_condition(&gs) {
+        if let Some((s, v)) = victory_condition(gs) {
             return Some((s, v));
         }
     }
@@ -373,7 +373,7 @@ fn load_input() -> Vec<Group> {
 }
 
 fn parse_string(s: &str) -> Vec<Group> {
-    let f = AoC24Parser::parse(Rule::file, &s)
+    let f = AoC24Parser::parse(Rule::file, s)
         .expect("failed to parse")
         .next()
         .unwrap();
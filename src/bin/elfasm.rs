@@ -0,0 +1,32 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Assemble ElfCode source with labels and register names into the plain
+//! puzzle format.
+//!
+//! Usage: `elfasm SOURCE`
+
+use aoc2018::elfcode::asm::assemble;
+
+pub fn main() {
+    let path = std::env::args().nth(1).expect("usage: elfasm SOURCE");
+    let src = std::fs::read_to_string(&path).unwrap();
+    match assemble(&src) {
+        Ok(prog) => print!("{}", prog),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compare the speed of the ElfCode interpreter and compiler.
+//!
+//! Run with `cargo run --release --bin elfbench`.
+
+use std::time::{Duration, Instant};
+
+use aoc2018::elfcode::compile::Compiled;
+use aoc2018::elfcode::{Program, Reg};
+
+fn load(path: &str, r0: Reg) -> Program {
+    let mut p: Program = std::fs::read_to_string(path).unwrap().parse().unwrap();
+    p.reg[0] = r0;
+    p
+}
+
+fn time<F: FnOnce() -> Program>(f: F) -> (Duration, Program) {
+    let start = Instant::now();
+    let p = f();
+    (start.elapsed(), p)
+}
+
+fn bench(path: &str, r0: Reg) {
+    let (t_interp, interp) = time(|| {
+        let mut p = load(path, r0);
+        p.eval();
+        p
+    });
+    let (t_comp, comp) = time(|| {
+        let mut p = load(path, r0);
+        Compiled::new(&p).eval(&mut p);
+        p
+    });
+    assert_eq!(interp.reg, comp.reg);
+    println!(
+        "{} with r0={}: interpreted {:?}, compiled {:?}, speedup {:.1}x",
+        path,
+        r0,
+        t_interp,
+        t_comp,
+        t_interp.as_secs_f64() / t_comp.as_secs_f64()
+    );
+}
+
+pub fn main() {
+    bench("input/input19.txt", 0);
+    // The slowest-halting input for day 21.
+    bench("input/input21.txt", 11777564);
+}
@@ -0,0 +1,53 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Interactive debugger for ElfCode programs.
+//!
+//! Usage: `elfdbg PROGRAM`, then type commands like `break 28`, `continue`,
+//! `watch r5`, `step 10` or `trace on`. Commands can also be piped in from a
+//! script.
+
+use std::io::prelude::*;
+
+use aoc2018::elfcode::debugger::Debugger;
+use aoc2018::elfcode::Program;
+
+pub fn main() {
+    let path = std::env::args().nth(1).expect("usage: elfdbg PROGRAM");
+    let prog: Program = match std::fs::read_to_string(&path).unwrap().parse() {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    let mut dbg = Debugger::new(prog);
+    println!("{}", dbg.status());
+    let stdin = std::io::stdin();
+    print!("> ");
+    std::io::stdout().flush().unwrap();
+    for line in stdin.lock().lines() {
+        let line = line.unwrap();
+        if line.trim() == "quit" {
+            break;
+        }
+        match dbg.command(&line) {
+            Ok(s) if s.is_empty() => (),
+            Ok(s) => println!("{}", s),
+            Err(e) => println!("error: {}", e),
+        }
+        print!("> ");
+        std::io::stdout().flush().unwrap();
+    }
+}
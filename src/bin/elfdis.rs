@@ -0,0 +1,44 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Print an annotated listing of an ElfCode program.
+//!
+//! Usage: `elfdis [--dot] PROGRAM`
+//!
+//! With `--dot`, print the control-flow graph in Graphviz format instead.
+
+use aoc2018::elfcode::cfg::Cfg;
+use aoc2018::elfcode::disasm::disassemble;
+use aoc2018::elfcode::Program;
+
+pub fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let dot = args.first().map(String::as_str) == Some("--dot");
+    if dot {
+        args.remove(0);
+    }
+    let path = args.first().expect("usage: elfdis [--dot] PROGRAM");
+    let prog: Program = match std::fs::read_to_string(path).unwrap().parse() {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    if dot {
+        print!("{}", Cfg::new(&prog).to_dot(&prog));
+    } else {
+        print!("{}", disassemble(&prog));
+    }
+}
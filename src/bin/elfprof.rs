@@ -0,0 +1,39 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Run an ElfCode program and show which instructions were hottest.
+//!
+//! Usage: `elfprof PROGRAM [R0]`
+
+use aoc2018::elfcode::Program;
+
+pub fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let path = args.first().expect("usage: elfprof PROGRAM [R0]");
+    let mut prog: Program = match std::fs::read_to_string(path).unwrap().parse() {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(r0) = args.get(1) {
+        prog.reg[0] = r0.parse().expect("R0 should be a number");
+    }
+    prog.start_profile();
+    if let Err(fault) = prog.run() {
+        println!("fault: {}", fault);
+    }
+    print!("{}", prog.profile.as_ref().unwrap().report(&prog));
+}
@@ -0,0 +1,787 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! ElfCode: the virtual machine from days 16, 19 and 21.
+//!
+//! Each instruction has an opcode and three arguments, A, B and C, and acts
+//! on a small bank of registers. The result is always stored into register C.
+//!
+//! A program may bind one register to the instruction pointer, with an `#ip`
+//! directive on its first line. Before each instruction the IP is written into
+//! that register, and afterwards it's read back and incremented, so
+//! instructions that write the bound register act as jumps.
+
+use std::fmt;
+use std::str::FromStr;
+
+pub mod asm;
+pub mod cfg;
+pub mod compile;
+pub mod debugger;
+pub mod disasm;
+pub mod halting;
+pub mod optimize;
+pub mod profile;
+pub mod replay;
+pub mod symbolic;
+
+use self::optimize::Idiom;
+use self::profile::Profile;
+use self::replay::Snapshot;
+
+/// The type of register values, and of instruction arguments.
+pub type Reg = usize;
+
+/// Number of registers in the day 19 and day 21 machines.
+pub const NREG: usize = 6;
+
+/// A syntax error in ElfCode source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column, in characters.
+    pub col: usize,
+    /// A description of what should have been there.
+    pub expected: &'static str,
+    /// What was actually there, or "end of line".
+    pub found: String,
+}
+
+impl ParseError {
+    fn new(col: usize, expected: &'static str, found: &str) -> ParseError {
+        ParseError {
+            line: 1,
+            col,
+            expected,
+            found: found.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: expected {}, found {:?}",
+            self.line, self.col, self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Split a line into words, with the 1-based column where each starts.
+fn words(s: &str) -> impl Iterator<Item = (usize, &str)> {
+    s.split_whitespace().map(move |w| {
+        (
+            s[..w.as_ptr() as usize - s.as_ptr() as usize]
+                .chars()
+                .count()
+                + 1,
+            w,
+        )
+    })
+}
+
+/// What to do when a result doesn't fit in a register.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Overflow {
+    /// Stop the program with a fault.
+    Trap,
+    /// Keep the low bits.
+    Wrap,
+    /// Use the largest value that fits.
+    Saturate,
+}
+
+/// The width of the machine's registers, and its overflow policy.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Word {
+    pub bits: u32,
+    pub overflow: Overflow,
+}
+
+impl Default for Word {
+    /// Native-width registers that trap on overflow.
+    fn default() -> Word {
+        Word::new(Reg::BITS, Overflow::Trap)
+    }
+}
+
+impl Word {
+    pub fn new(bits: u32, overflow: Overflow) -> Word {
+        assert!(bits > 0 && bits <= Reg::BITS, "bad word size {}", bits);
+        Word { bits, overflow }
+    }
+
+    /// The largest value a register can hold.
+    pub fn max(self) -> Reg {
+        Reg::MAX >> (Reg::BITS - self.bits)
+    }
+
+    /// Fit a result into a register, or None if it overflows and that
+    /// should trap.
+    fn fit(self, v: u128) -> Option<Reg> {
+        let max = self.max() as u128;
+        if v <= max {
+            Some(v as Reg)
+        } else {
+            match self.overflow {
+                Overflow::Trap => None,
+                Overflow::Wrap => Some((v & max) as Reg),
+                Overflow::Saturate => Some(max as Reg),
+            }
+        }
+    }
+}
+
+/// Why an instruction couldn't run.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FaultKind {
+    /// The result didn't fit, and the policy is to trap.
+    Overflow,
+    /// The instruction names a register that doesn't exist.
+    BadRegister(usize),
+}
+
+/// An instruction that stopped the program, and the machine state when it
+/// did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub ip: usize,
+    pub inst: Inst,
+    /// Registers as the instruction saw them, including the IP register.
+    pub reg: Vec<Reg>,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            FaultKind::Overflow => write!(f, "overflow")?,
+            FaultKind::BadRegister(r) => write!(f, "no register {}", r)?,
+        }
+        write!(
+            f,
+            " in `{}` at ip {}, registers {:?}",
+            self.inst, self.ip, self.reg
+        )
+    }
+}
+
+impl std::error::Error for Fault {}
+
+/// How an opcode interprets its A or B argument.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operand {
+    /// A register number.
+    Reg,
+    /// A literal value.
+    Imm,
+    /// Not used.
+    Unused,
+}
+
+/// An instruction
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum Opcode {
+    /// addr (add register) stores into register C the result of adding register A and register B.
+    Addr,
+    /// addi (add immediate) stores into register C the result of adding register A and value B.
+    Addi,
+    /// mulr (multiply register) stores into register C the result of multiplying register A and register B.
+    Mulr,
+    /// muli (multiply immediate) stores into register C the result of multiplying register A and value B.
+    Muli,
+    /// banr (bitwise AND register) stores into register C the result of the bitwise AND of register A and register B.
+    Banr,
+    /// bani (bitwise AND immediate) stores into register C the result of the bitwise AND of register A and value B.
+    Bani,
+    /// borr (bitwise OR register) stores into register C the result of the bitwise OR of register A and register B.
+    Borr,
+    /// bori (bitwise OR immediate) stores into register C the result of the bitwise OR of register A and value B.
+    Bori,
+    /// setr (set register) copies the contents of register A into register C. (Input B is ignored.)
+    Setr,
+    /// seti (set immediate) stores value A into register C. (Input B is ignored.)
+    Seti,
+    /// gtir (greater-than immediate/register) sets register C to 1 if value A is greater than register B. Otherwise, register C is set to 0.
+    Gtir,
+    /// gtri (greater-than register/immediate) sets register C to 1 if register A is greater than value B. Otherwise, register C is set to 0.
+    Gtri,
+    /// gtrr (greater-than register/register) sets register C to 1 if register A is greater than register B. Otherwise, register C is set to 0.
+    Gtrr,
+    /// eqir (equal immediate/register) sets register C to 1 if value A is equal to register B. Otherwise, register C is set to 0.
+    Eqir,
+    /// eqri (equal register/immediate) sets register C to 1 if register A is equal to value B. Otherwise, register C is set to 0.
+    Eqri,
+    /// eqrr (equal register/register) sets register C to 1 if register A is equal to register B. Otherwise, register C is set to 0.
+    Eqrr,
+}
+use self::Opcode::*;
+
+impl Opcode {
+    pub fn all() -> &'static [Opcode] {
+        &[
+            Muli, Mulr, Addr, Addi, Banr, Bani, Borr, Bori, Setr, Seti, Gtir, Gtri, Gtrr, Eqir,
+            Eqri, Eqrr,
+        ]
+    }
+
+    /// The assembler mnemonic, like `"addr"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Addr => "addr",
+            Addi => "addi",
+            Mulr => "mulr",
+            Muli => "muli",
+            Banr => "banr",
+            Bani => "bani",
+            Borr => "borr",
+            Bori => "bori",
+            Setr => "setr",
+            Seti => "seti",
+            Gtir => "gtir",
+            Gtri => "gtri",
+            Gtrr => "gtrr",
+            Eqir => "eqir",
+            Eqri => "eqri",
+            Eqrr => "eqrr",
+        }
+    }
+
+    /// How this opcode interprets its A and B arguments.
+    pub fn operands(self) -> (Operand, Operand) {
+        use self::Operand::*;
+        match self {
+            Addr | Mulr | Banr | Borr | Gtrr | Eqrr => (Reg, Reg),
+            Addi | Muli | Bani | Bori | Gtri | Eqri => (Reg, Imm),
+            Gtir | Eqir => (Imm, Reg),
+            Setr => (Reg, Unused),
+            Seti => (Imm, Unused),
+        }
+    }
+
+    /// True for the comparisons, which always produce 0 or 1.
+    pub fn is_comparison(self) -> bool {
+        matches!(self, Gtir | Gtri | Gtrr | Eqir | Eqri | Eqrr)
+    }
+
+    /// Compute the value this opcode would store into register C, given
+    /// arguments A and B, with native-width registers.
+    ///
+    /// Panics on overflow or a bad register number.
+    pub fn eval(self, r: &[Reg], a: Reg, b: Reg) -> Reg {
+        self.eval_in(Word::default(), r, a, b)
+            .unwrap_or_else(|kind| panic!("{:?} in {} {} {}", kind, self, a, b))
+    }
+
+    /// Compute the value this opcode would store into register C, on a
+    /// machine with the given word.
+    pub fn eval_in(self, word: Word, r: &[Reg], a: Reg, b: Reg) -> Result<Reg, FaultKind> {
+        let (oa, ob) = self.operands();
+        let arg = |o, x: Reg| match o {
+            Operand::Reg => r
+                .get(x)
+                .map(|v| *v as u128)
+                .ok_or(FaultKind::BadRegister(x)),
+            _ => Ok(x as u128),
+        };
+        let x = arg(oa, a)?;
+        let y = arg(ob, b)?;
+        // Happily in Rust if you cast a bool to an int, it goes to 1 and 0.
+        let v = match self {
+            Muli | Mulr => x * y,
+            Addr | Addi => x + y,
+            Banr | Bani => x & y,
+            Borr | Bori => x | y,
+            Setr | Seti => x,
+            Gtir | Gtri | Gtrr => (x > y) as u128,
+            Eqir | Eqri | Eqrr => (x == y) as u128,
+        };
+        word.fit(v).ok_or(FaultKind::Overflow)
+    }
+}
+
+impl FromStr for Opcode {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Opcode::all()
+            .iter()
+            .find(|op| op.name() == s)
+            .cloned()
+            .ok_or_else(|| ParseError::new(1, "opcode", s))
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// An instruction with opcode and arguments.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Inst {
+    pub opcode: Opcode,
+    pub a: Reg,
+    pub b: Reg,
+    pub c: Reg,
+}
+
+impl Inst {
+    /// Apply an instruction to some registers, updating them in place.
+    pub fn apply(&self, r: &mut [Reg]) {
+        r[self.c] = self.opcode.eval(r, self.a, self.b);
+    }
+
+    /// Apply an instruction on a machine with the given word, or say why it
+    /// can't run. Registers are unchanged on failure.
+    pub fn exec(&self, word: Word, r: &mut [Reg]) -> Result<(), FaultKind> {
+        let v = self.opcode.eval_in(word, r, self.a, self.b)?;
+        *r.get_mut(self.c).ok_or(FaultKind::BadRegister(self.c))? = v;
+        Ok(())
+    }
+
+    /// The registers this instruction reads.
+    pub fn reads(&self) -> Vec<usize> {
+        let (oa, ob) = self.opcode.operands();
+        let mut v = Vec::with_capacity(2);
+        if oa == Operand::Reg {
+            v.push(self.a);
+        }
+        if ob == Operand::Reg && !v.contains(&self.b) {
+            v.push(self.b);
+        }
+        v
+    }
+}
+
+impl FromStr for Inst {
+    type Err = ParseError;
+
+    /// Parse one instruction like `addi 4 13 4`. Errors are reported on
+    /// line 1.
+    fn from_str(s: &str) -> Result<Inst, ParseError> {
+        let eol = s.trim_end().chars().count() + 1;
+        let mut words = words(s);
+        let mut next = |expected| {
+            words
+                .next()
+                .ok_or_else(|| ParseError::new(eol, expected, "end of line"))
+        };
+        let (col, w) = next("opcode")?;
+        let opcode = Opcode::from_str(w).map_err(|e| ParseError { col, ..e })?;
+        let mut arg = |expected| {
+            let (col, w) = next(expected)?;
+            w.parse().map_err(|_| ParseError::new(col, expected, w))
+        };
+        let a = arg("number for argument A")?;
+        let b = arg("number for argument B")?;
+        let c = arg("register number for argument C")?;
+        if let Some((col, w)) = words.next() {
+            return Err(ParseError::new(col, "end of line", w));
+        }
+        Ok(Inst { opcode, a, b, c })
+    }
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.opcode, self.a, self.b, self.c)
+    }
+}
+
+/// A loaded program, plus the state of the machine running it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    /// Index of the register bound to the IP, if any.
+    pub ip_reg: Option<usize>,
+
+    /// Current instruction pointer.
+    pub ip: usize,
+
+    /// All the registers.
+    pub reg: Vec<Reg>,
+
+    /// Instructions
+    pub code: Vec<Inst>,
+
+    /// Register width and overflow policy.
+    pub word: Word,
+
+    /// Set if an instruction failed, which stops the program.
+    pub fault: Option<Fault>,
+
+    /// Execution counts, if profiling is on.
+    pub profile: Option<Profile>,
+
+    /// Loops that can be run natively, indexed by their start address.
+    /// Empty unless the program has been optimized.
+    idioms: Vec<Option<Idiom>>,
+}
+
+impl FromStr for Program {
+    type Err = ParseError;
+
+    /// Parse a program with `NREG` registers and an optional leading `#ip`
+    /// directive.
+    fn from_str(s: &str) -> Result<Program, Self::Err> {
+        let mut ip_reg = None;
+        let mut code = Vec::new();
+        for (i, l) in s.lines().enumerate() {
+            let at_line = |e| ParseError { line: i + 1, ..e };
+            if i == 0 && l.starts_with('#') {
+                let mut words = words(l);
+                let (_, directive) = words.next().unwrap();
+                if directive != "#ip" {
+                    return Err(at_line(ParseError::new(1, "#ip", directive)));
+                }
+                let eol = l.trim_end().chars().count() + 1;
+                let (col, r) = words
+                    .next()
+                    .ok_or_else(|| at_line(ParseError::new(eol, "IP register", "end of line")))?;
+                match r.parse() {
+                    Ok(r) if r < NREG => ip_reg = Some(r),
+                    _ => return Err(at_line(ParseError::new(col, "IP register", r))),
+                }
+                if let Some((col, w)) = words.next() {
+                    return Err(at_line(ParseError::new(col, "end of line", w)));
+                }
+            } else if !l.trim().is_empty() {
+                code.push(l.parse().map_err(at_line)?);
+            }
+        }
+        Ok(Program::new(NREG, ip_reg, code))
+    }
+}
+
+impl fmt::Display for Program {
+    /// Write the code in the puzzle input format.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(r) = self.ip_reg {
+            writeln!(f, "#ip {}", r)?;
+        }
+        for inst in &self.code {
+            writeln!(f, "{}", inst)?;
+        }
+        Ok(())
+    }
+}
+
+impl Program {
+    /// Make a new program with `nreg` registers, all zero, and the IP at the
+    /// start.
+    pub fn new(nreg: usize, ip_reg: Option<usize>, code: Vec<Inst>) -> Program {
+        if let Some(ip_reg) = ip_reg {
+            assert!(ip_reg < nreg, "#ip {} out of range", ip_reg);
+        }
+        Program {
+            ip_reg,
+            ip: 0,
+            reg: vec![0; nreg],
+            code,
+            word: Word::default(),
+            fault: None,
+            profile: None,
+            idioms: Vec::new(),
+        }
+    }
+
+    /// Look for loops that can be computed natively rather than
+    /// interpreted, and use them from now on.
+    ///
+    /// The registers and IP after running each loop will be exactly the same
+    /// as the interpreter would produce, but it will take only one step.
+    ///
+    /// The loops are computed with native arithmetic, so this does nothing
+    /// unless the registers are native width, and they're ignored if `word`
+    /// is later narrowed. A loop that would overflow is left to the
+    /// interpreter, so it faults or wraps as usual.
+    pub fn optimize(&mut self) {
+        if self.word.bits == Reg::BITS {
+            self.idioms = optimize::find_idioms(self);
+        }
+    }
+
+    /// Start counting the instructions executed from now on, discarding
+    /// any previous profile.
+    ///
+    /// Runs of optimized loops are counted separately from the instructions
+    /// in them.
+    pub fn start_profile(&mut self) {
+        self.profile = Some(Profile::new(self.code.len(), self.reg.len()));
+    }
+
+    /// Save the IP, registers and any fault, to return to later.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            ip: self.ip,
+            reg: self.reg.clone(),
+            fault: self.fault.clone(),
+        }
+    }
+
+    /// Go back to a saved state.
+    pub fn restore(&mut self, s: &Snapshot) {
+        self.ip = s.ip;
+        self.reg.clone_from(&s.reg);
+        self.fault.clone_from(&s.fault);
+    }
+
+    /// True if the IP has moved outside the program, or it's stopped on a
+    /// fault.
+    pub fn is_halted(&self) -> bool {
+        self.ip >= self.code.len() || self.fault.is_some()
+    }
+
+    /// The instruction that will run next, if the program hasn't halted.
+    pub fn current(&self) -> Option<&Inst> {
+        self.code.get(self.ip)
+    }
+
+    /// Execute one instruction; return true if the IP is still in bounds
+    /// and there was no fault.
+    ///
+    /// On a fault the IP and registers are left as they were before the
+    /// instruction, except that the IP register holds the IP.
+    pub fn step(&mut self) -> bool {
+        if self.is_halted() {
+            return false;
+        }
+        // The idioms use native arithmetic, so they're only valid while the
+        // word is still native width.
+        let idiom = match self.idioms.get(self.ip) {
+            Some(&Some(idiom)) if self.word.bits == Reg::BITS => Some(idiom),
+            _ => None,
+        };
+        if let Some(idiom) = idiom {
+            let ip = self.ip;
+            if idiom.apply(self) {
+                if let Some(profile) = &mut self.profile {
+                    profile.record_idiom(ip);
+                }
+                return !self.is_halted();
+            }
+        }
+        if let Some(ip_reg) = self.ip_reg {
+            self.reg[ip_reg] = self.ip;
+        }
+        let inst = self.code[self.ip];
+        if let Err(kind) = inst.exec(self.word, &mut self.reg) {
+            self.fault = Some(Fault {
+                kind,
+                ip: self.ip,
+                inst,
+                reg: self.reg.clone(),
+            });
+            return false;
+        }
+        if let Some(profile) = &mut self.profile {
+            profile.record(self.ip, inst.c);
+        }
+        if let Some(ip_reg) = self.ip_reg {
+            self.ip = self.reg[ip_reg];
+        }
+        self.ip = self.ip.saturating_add(1);
+        !self.is_halted()
+    }
+
+    /// Step until `f` returns true, checked before each instruction, or
+    /// until the program halts.
+    ///
+    /// Returns true if `f` matched, or false if the program halted.
+    pub fn run_until<F>(&mut self, mut f: F) -> bool
+    where
+        F: FnMut(&Program) -> bool,
+    {
+        while !self.is_halted() {
+            if f(self) {
+                return true;
+            }
+            self.step();
+        }
+        false
+    }
+
+    /// Run the program as long as the IP is valid; then return the contents of
+    /// register 0.
+    ///
+    /// Panics if the program faults.
+    pub fn eval(&mut self) -> Reg {
+        self.run().unwrap_or_else(|fault| panic!("{}", fault))
+    }
+
+    /// Run the program until it halts and return register 0, or until it
+    /// faults and return the fault.
+    pub fn run(&mut self) -> Result<Reg, Fault> {
+        while self.step() {}
+        match &self.fault {
+            Some(fault) => Err(fault.clone()),
+            None => Ok(self.reg[0]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXAMPLE: &str = "#ip 0
+seti 5 0 1
+seti 6 0 2
+addi 0 1 0
+addr 1 2 3
+setr 1 0 0
+seti 8 0 4
+seti 9 0 5
+";
+
+    #[test]
+    fn parse_opcode() {
+        assert_eq!("seti".parse(), Ok(Seti));
+        assert_eq!("nope".parse::<Opcode>().unwrap_err().expected, "opcode");
+        for op in Opcode::all() {
+            assert_eq!(op.name().parse(), Ok(*op));
+        }
+    }
+
+    #[test]
+    fn parse_inst() {
+        assert_eq!(
+            "addi 4 13 4".parse(),
+            Ok(Inst {
+                opcode: Addi,
+                a: 4,
+                b: 13,
+                c: 4
+            })
+        )
+    }
+
+    #[test]
+    fn parse_errors() {
+        let e = |s: &str| s.parse::<Program>().unwrap_err();
+        assert_eq!(
+            e("#ip 1\nseti 1 0 2\naddi 4 x 4\n"),
+            ParseError {
+                line: 3,
+                col: 8,
+                expected: "number for argument B",
+                found: "x".to_owned()
+            }
+        );
+        assert_eq!(
+            e("seti 1 0 2\n  ad 1 2 3").to_string(),
+            "line 2, column 3: expected opcode, found \"ad\""
+        );
+        let err = e("addi 1 2");
+        assert_eq!((err.line, err.col), (1, 9));
+        assert_eq!(err.found, "end of line");
+        assert_eq!(e("addi 1 2 3 4").col, 12);
+        assert_eq!(e("#ip 9").expected, "IP register");
+        assert_eq!(e("#ip").col, 4);
+        assert_eq!(e("#foo 1").expected, "#ip");
+        // #ip is only allowed on the first line.
+        assert_eq!(e("seti 1 0 2\n#ip 2").found, "#ip");
+    }
+
+    #[test]
+    fn simple_inst() {
+        // muli (multiply immediate) stores into register C the result of
+        // multiplying register A and value B.
+        // r0 = r2 * 10
+        let mut r = [1, 2, 3, 4];
+        Inst {
+            opcode: Muli,
+            a: 2,
+            b: 10,
+            c: 0,
+        }
+        .apply(&mut r);
+        assert_eq!(r, [30, 2, 3, 4]);
+
+        // r0 = (r2 == r3)
+        assert_eq!(Eqrr.eval(&[10, 20, 30, 40], 2, 3), 0);
+        assert_eq!(Eqrr.eval(&[10, 20, 30, 30], 2, 3), 1);
+    }
+
+    #[test]
+    fn example() {
+        let mut p = Program::from_str(EXAMPLE).unwrap();
+        assert_eq!(p.ip_reg, Some(0));
+        assert_eq!(p.reg.len(), NREG);
+        assert_eq!(p.code.len(), 7);
+        assert_eq!(p.code[4].to_string(), "setr 1 0 0");
+        assert_eq!(p.eval(), 6);
+        assert_eq!(p.reg, vec![6, 5, 6, 0, 0, 9]);
+    }
+
+    #[test]
+    fn run_until() {
+        let mut p = Program::from_str(EXAMPLE).unwrap();
+        assert!(p.run_until(|p| p.ip == 4));
+        assert_eq!(p.reg[1], 5);
+        assert!(!p.run_until(|p| p.ip == 2));
+    }
+
+    #[test]
+    fn word_sizes() {
+        let run = |word| {
+            let mut p: Program = "muli 0 4096 0\nmuli 0 4096 0\naddi 0 1 0\n"
+                .parse()
+                .unwrap();
+            p.reg[0] = 5;
+            p.word = word;
+            p.run()
+        };
+        assert_eq!(run(Word::default()), Ok(5 << 24 | 1));
+        assert_eq!(run(Word::new(32, Overflow::Trap)), Ok(5 << 24 | 1));
+        assert_eq!(run(Word::new(24, Overflow::Wrap)), Ok(1));
+        assert_eq!(run(Word::new(24, Overflow::Saturate)), Ok(0xff_ffff));
+        let fault = run(Word::new(24, Overflow::Trap)).unwrap_err();
+        assert_eq!(fault.kind, FaultKind::Overflow);
+        assert_eq!(fault.ip, 1);
+        assert_eq!(fault.reg, vec![5 << 12, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            fault.to_string(),
+            "overflow in `muli 0 4096 0` at ip 1, registers [20480, 0, 0, 0, 0, 0]"
+        );
+        // Native-width overflow traps by default, rather than panicking.
+        let mut p: Program = "mulr 0 0 0".parse().unwrap();
+        p.reg[0] = 1 << 40;
+        assert_eq!(p.run().unwrap_err().kind, FaultKind::Overflow);
+    }
+
+    #[test]
+    fn bad_register() {
+        let mut p: Program = "#ip 2\nseti 3 0 1\nbanr 0 9 3\n".parse().unwrap();
+        let fault = p.run().unwrap_err();
+        assert_eq!(fault.kind, FaultKind::BadRegister(9));
+        assert_eq!((fault.ip, p.ip), (1, 1));
+        assert_eq!(fault.reg, vec![0, 3, 1, 0, 0, 0]);
+        assert!(p.is_halted());
+        assert!(!p.step());
+        let mut p: Program = "seti 1 0 6".parse().unwrap();
+        assert_eq!(p.run().unwrap_err().kind, FaultKind::BadRegister(6));
+    }
+
+    #[test]
+    fn no_ip_binding() {
+        let mut p = Program::new(4, None, vec!["seti 7 0 3".parse().unwrap()]);
+        assert!(!p.step());
+        assert_eq!(p.reg, vec![0, 0, 0, 7]);
+    }
+}
@@ -0,0 +1,358 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Assemble ElfCode from a friendlier source language.
+//!
+//! On top of the plain puzzle format this accepts:
+//!
+//! * `;` comments, to the end of the line.
+//! * `name:` labels, before an instruction or on a line of their own. Labels
+//!   can be used as literal arguments, optionally with an offset like
+//!   `loop-1`.
+//! * `#reg name N` to give register N a name. Registers can also be written
+//!   as `rN`, and once there's an `#ip N` directive, as `ip`.
+//! * `jmp label`, which continues at the label by setting the IP register.
+//! * `setr` and `seti` with only two arguments, since B is ignored.
+//!
+//! ```
+//! use aoc2018::elfcode::asm::assemble;
+//!
+//! let mut p = assemble("
+//!     ; Sum the numbers from 1 to 10.
+//!     #ip 5
+//!     #reg i 1
+//!             seti 1 i
+//!     loop:   addr r0 i r0
+//!             addi i 1 i
+//!             gtri i 10 r2
+//!             addr r2 ip ip   ; finish if i > 10
+//!             jmp loop
+//! ").unwrap();
+//! assert_eq!(p.code[5].to_string(), "seti 0 0 5");
+//! assert_eq!(p.eval(), 55);
+//! ```
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use super::{words, Inst, Opcode, Operand, ParseError, Program, Reg, NREG};
+
+/// A word of source, with the 1-based column where it starts.
+type Token<'a> = (usize, &'a str);
+
+/// One source line, with the comment and any label removed.
+struct Line<'a> {
+    number: usize,
+    /// Column just past the last word.
+    eol: usize,
+    words: Vec<Token<'a>>,
+}
+
+impl<'a> Line<'a> {
+    /// Check there are exactly `n` words, the last of which should be
+    /// `expected`.
+    fn arity(&self, n: usize, expected: &'static str) -> Result<(), ParseError> {
+        match self.words.get(n) {
+            Some(&(col, w)) => Err(ParseError::new(col, "end of line", w)),
+            None if self.words.len() < n => Err(ParseError::new(self.eol, expected, "end of line")),
+            None => Ok(()),
+        }
+    }
+}
+
+struct Assembler {
+    ip_reg: Option<usize>,
+    aliases: BTreeMap<String, usize>,
+    labels: BTreeMap<String, usize>,
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Split `s`, which starts at 0-based column `offset`, into tokens.
+fn tokens(s: &str, offset: usize) -> Vec<Token<'_>> {
+    words(s).map(|(col, w)| (col + offset, w)).collect()
+}
+
+impl Assembler {
+    fn reg(&self, (col, s): Token) -> Result<usize, ParseError> {
+        let r = if let Some(&r) = self.aliases.get(s) {
+            r
+        } else if s == "ip" {
+            self.ip_reg
+                .ok_or_else(|| ParseError::new(col, "register, after an #ip directive", s))?
+        } else {
+            s.strip_prefix('r')
+                .unwrap_or(s)
+                .parse()
+                .map_err(|_| ParseError::new(col, "register", s))?
+        };
+        if r < NREG {
+            Ok(r)
+        } else {
+            Err(ParseError::new(col, "register", s))
+        }
+    }
+
+    fn literal(&self, (col, s): Token) -> Result<Reg, ParseError> {
+        if let Ok(v) = s.parse() {
+            return Ok(v);
+        }
+        let (name, offset) = match s.find(['+', '-']) {
+            Some(i) => {
+                let off: isize = s[i..]
+                    .parse()
+                    .map_err(|_| ParseError::new(col, "label with a numeric offset", s))?;
+                (&s[..i], off)
+            }
+            None => (s, 0),
+        };
+        let addr = *self
+            .labels
+            .get(name)
+            .ok_or_else(|| ParseError::new(col, "number or label", s))?;
+        match (addr as isize).checked_add(offset) {
+            Some(v) if v >= 0 => Ok(v as Reg),
+            _ => Err(ParseError::new(col, "non-negative address", s)),
+        }
+    }
+
+    fn arg(&self, o: Operand, t: Option<&Token>) -> Result<Reg, ParseError> {
+        match (o, t) {
+            (Operand::Reg, Some(&t)) => self.reg(t),
+            (_, Some(&t)) => self.literal(t),
+            (_, None) => Ok(0),
+        }
+    }
+
+    fn inst(&self, line: &Line) -> Result<Inst, ParseError> {
+        let (col, name) = line.words[0];
+        if name == "jmp" {
+            let ip_reg = self
+                .ip_reg
+                .ok_or_else(|| ParseError::new(col, "#ip before jmp", name))?;
+            line.arity(2, "label")?;
+            let target = self.literal(line.words[1])?;
+            if target == 0 {
+                // Would need to set the IP to -1.
+                let (col, w) = line.words[1];
+                return Err(ParseError::new(col, "label after address 0", w));
+            }
+            return Ok(Inst {
+                opcode: Opcode::Seti,
+                a: target - 1,
+                b: 0,
+                c: ip_reg,
+            });
+        }
+        let opcode = Opcode::from_str(name).map_err(|e| ParseError { col, ..e })?;
+        let (oa, ob) = opcode.operands();
+        let args = &line.words[1..];
+        let (a, b, c) = if ob == Operand::Unused && args.len() == 2 {
+            (args.first(), None, args.get(1))
+        } else {
+            line.arity(4, "register for argument C")?;
+            (args.first(), args.get(1), args.get(2))
+        };
+        Ok(Inst {
+            opcode,
+            a: self.arg(oa, a)?,
+            b: self.arg(ob, b)?,
+            c: self.arg(Operand::Reg, c)?,
+        })
+    }
+
+    /// Handle a `#` directive.
+    fn directive(&mut self, line: &Line) -> Result<(), ParseError> {
+        let (col, name) = line.words[0];
+        match name {
+            "#ip" => {
+                if self.ip_reg.is_some() {
+                    return Err(ParseError::new(col, "only one #ip", name));
+                }
+                line.arity(2, "register")?;
+                self.ip_reg = Some(self.reg(line.words[1])?);
+                Ok(())
+            }
+            "#reg" => {
+                line.arity(3, "register")?;
+                let (col, alias) = line.words[1];
+                if !is_ident(alias) || alias == "ip" {
+                    return Err(ParseError::new(col, "register name", alias));
+                }
+                let r = self.reg(line.words[2])?;
+                self.aliases.insert(alias.to_owned(), r);
+                Ok(())
+            }
+            _ => Err(ParseError::new(col, "#ip or #reg", name)),
+        }
+    }
+}
+
+/// Assemble source into a program with `NREG` registers.
+///
+/// Labels can be used anywhere, but directives only affect the lines after
+/// them.
+pub fn assemble(src: &str) -> Result<Program, ParseError> {
+    let mut asm = Assembler {
+        ip_reg: None,
+        aliases: BTreeMap::new(),
+        labels: BTreeMap::new(),
+    };
+    // First pass: strip comments and find labels, so they can be used before
+    // they're defined.
+    let mut lines = Vec::new();
+    let mut addr = 0;
+    for (i, l) in src.lines().enumerate() {
+        let number = i + 1;
+        let at_line = |e| ParseError { line: number, ..e };
+        let l = l.split(';').next().unwrap();
+        let mut words = tokens(l, 0);
+        if let Some(colon) = l.find(':') {
+            let label = l[..colon].trim();
+            let col = words.first().map_or(colon + 1, |t| t.0);
+            if !is_ident(label) {
+                return Err(at_line(ParseError::new(col, "label", label)));
+            }
+            if asm.labels.insert(label.to_owned(), addr).is_some() {
+                return Err(at_line(ParseError::new(col, "new label", label)));
+            }
+            words = tokens(&l[colon + 1..], l[..=colon].chars().count());
+        }
+        if words.is_empty() {
+            continue;
+        }
+        if !words[0].1.starts_with('#') {
+            addr += 1;
+        }
+        let eol = l.trim_end().chars().count() + 1;
+        lines.push(Line { number, eol, words });
+    }
+    // Second pass: apply directives and assemble instructions in order.
+    let mut code = Vec::with_capacity(addr);
+    for l in lines {
+        let at_line = |e| ParseError {
+            line: l.number,
+            ..e
+        };
+        if l.words[0].1.starts_with('#') {
+            asm.directive(&l).map_err(at_line)?;
+        } else {
+            code.push(asm.inst(&l).map_err(at_line)?);
+        }
+    }
+    Ok(Program::new(NREG, asm.ip_reg, code))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn plain_puzzle_input() {
+        let src = include_str!("../../input/input21.txt");
+        let p = assemble(src).unwrap();
+        assert_eq!(p, src.parse().unwrap());
+        assert_eq!(p.to_string(), src);
+    }
+
+    #[test]
+    fn labels_and_aliases() {
+        let p = assemble(
+            "
+            #ip 4
+            #reg n 3
+            start:  seti 10 n       ; countdown
+            top:
+                    addi n 0 r1
+                    eqri r1 0 r2
+                    addr ip r2 ip
+                    seti top-1 0 ip
+                    jmp top
+            ",
+        )
+        .unwrap();
+        assert_eq!(p.ip_reg, Some(4));
+        let listing: Vec<String> = p.code.iter().map(Inst::to_string).collect();
+        assert_eq!(
+            listing,
+            vec![
+                "seti 10 0 3",
+                "addi 3 0 1",
+                "eqri 1 0 2",
+                "addr 4 2 4",
+                "seti 0 0 4",
+                "seti 0 0 4",
+            ]
+        );
+    }
+
+    #[test]
+    fn directives_in_order() {
+        let p = assemble("#reg n 1\nseti 5 n\n#reg n 2\nseti 6 n\n").unwrap();
+        assert_eq!(p.code[0].c, 1);
+        assert_eq!(p.code[1].c, 2);
+        let e = assemble("seti 1 ip\n#ip 3\n").unwrap_err();
+        assert_eq!((e.line, e.col, e.found.as_str()), (1, 8, "ip"));
+    }
+
+    #[test]
+    fn errors() {
+        let e = |s| assemble(s).unwrap_err();
+        assert_eq!(
+            e("seti 1 0 0\n  frob 1 2 3"),
+            ParseError {
+                line: 2,
+                col: 3,
+                expected: "opcode",
+                found: "frob".to_owned()
+            }
+        );
+        assert_eq!(
+            e("addr 1 2").to_string(),
+            "line 1, column 9: expected register for argument C, found \"end of line\""
+        );
+        assert_eq!(
+            e("addr 1 2 3 4").to_string(),
+            "line 1, column 12: expected end of line, found \"4\""
+        );
+        assert_eq!(e("addr 1 2 9").col, 10);
+        assert_eq!(e("addr 1 2 9").expected, "register");
+        assert_eq!(e("addi 1 nowhere 2").expected, "number or label");
+        assert_eq!(e("jmp x\nx: seti 1 0 0").expected, "#ip before jmp");
+        assert_eq!(e("#ip 1\nx: jmp x").col, 8);
+        assert_eq!(
+            e("x: seti 1 1\n x: seti 1 1").to_string(),
+            "line 2, column 2: expected new label, found \"x\""
+        );
+        assert_eq!(e("#ip 1\n#ip 2").expected, "only one #ip");
+        assert_eq!(e("#reg 1 2").expected, "register name");
+        assert_eq!(e("#frob").expected, "#ip or #reg");
+        assert_eq!(e("1x: seti 1 1").expected, "label");
+        assert_eq!(
+            e("x: seti 0 1\nseti x-1 1").to_string(),
+            "line 2, column 6: expected non-negative address, found \"x-1\""
+        );
+        assert_eq!(
+            e("seti 0 1\nx: seti 0 1\nseti x+9223372036854775807 1").to_string(),
+            "line 3, column 6: expected non-negative address, \
+             found \"x+9223372036854775807\""
+        );
+    }
+}
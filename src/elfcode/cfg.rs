@@ -0,0 +1,314 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Control-flow graph of an ElfCode program.
+//!
+//! The program is split into basic blocks at jump targets and after jumps.
+//! Jumps whose target can be worked out statically become edges; computed
+//! jumps are flagged on their block. Loops are found as back edges to a
+//! dominating block.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use super::disasm::{flow, jump_targets, pseudo, Flow};
+use super::Program;
+
+/// A straight-line run of instructions, entered only at the top.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    /// Address of the first instruction.
+    pub start: usize,
+    /// Address after the last instruction.
+    pub end: usize,
+    /// Indexes of blocks that can run next.
+    pub succs: Vec<usize>,
+    /// True if the block can jump outside the program.
+    pub halts: bool,
+    /// True if the block ends in a jump computed at runtime, so its
+    /// successors are unknown.
+    pub dynamic: bool,
+}
+
+/// A natural loop: a header block that dominates all the blocks in the body,
+/// and which is the target of a back edge from within the body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    /// Index of the header block.
+    pub header: usize,
+    /// Indexes of all blocks in the loop, including the header.
+    pub body: BTreeSet<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    pub blocks: Vec<Block>,
+}
+
+impl Cfg {
+    pub fn new(prog: &Program) -> Cfg {
+        let code = &prog.code;
+        let ip_reg = prog.ip_reg;
+        let mut leaders = jump_targets(ip_reg, code);
+        leaders.insert(0);
+        for pc in 0..code.len() {
+            if flow(ip_reg, code, pc) != Flow::Next {
+                leaders.insert(pc + 1);
+            }
+        }
+        let starts: Vec<usize> = leaders.into_iter().filter(|a| *a < code.len()).collect();
+        let block_at = |addr: usize| starts.binary_search(&addr).ok();
+        let mut blocks = Vec::with_capacity(starts.len());
+        for (i, &start) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).cloned().unwrap_or(code.len());
+            let mut b = Block {
+                start,
+                end,
+                succs: Vec::new(),
+                halts: false,
+                dynamic: false,
+            };
+            let mut targets = Vec::new();
+            match flow(ip_reg, code, end - 1) {
+                Flow::Next => targets.push(end),
+                Flow::Goto(t) => targets.push(t),
+                Flow::Branch { target, .. } => {
+                    targets.push(end);
+                    targets.push(target);
+                }
+                Flow::Dynamic => b.dynamic = true,
+            }
+            for t in targets {
+                match block_at(t) {
+                    Some(j) => b.succs.push(j),
+                    None => b.halts = true,
+                }
+            }
+            blocks.push(b);
+        }
+        Cfg { blocks }
+    }
+
+    /// Index of the block containing an address.
+    pub fn block_containing(&self, addr: usize) -> Option<usize> {
+        self.blocks
+            .iter()
+            .position(|b| b.start <= addr && addr < b.end)
+    }
+
+    /// Indexes of blocks that can run immediately before each block.
+    pub fn preds(&self) -> Vec<Vec<usize>> {
+        let mut preds = vec![Vec::new(); self.blocks.len()];
+        for (i, b) in self.blocks.iter().enumerate() {
+            for &s in &b.succs {
+                preds[s].push(i);
+            }
+        }
+        preds
+    }
+
+    /// Blocks where execution can start: the first block, plus any block
+    /// with no static predecessors, which can only be reached by a
+    /// computed jump (or is dead).
+    pub fn entries(&self) -> Vec<usize> {
+        let preds = self.preds();
+        (0..self.blocks.len())
+            .filter(|i| *i == 0 || preds[*i].is_empty())
+            .collect()
+    }
+
+    /// Indexes of blocks reachable from any entry.
+    pub fn reachable(&self) -> BTreeSet<usize> {
+        let mut seen = BTreeSet::new();
+        let mut stack = self.entries();
+        while let Some(i) = stack.pop() {
+            if seen.insert(i) {
+                stack.extend(&self.blocks[i].succs);
+            }
+        }
+        seen
+    }
+
+    /// For each block, the set of blocks that dominate it: that is, that
+    /// are on every path from an entry to it.
+    ///
+    /// Blocks that can't be reached are dominated by everything.
+    pub fn dominators(&self) -> Vec<BTreeSet<usize>> {
+        let n = self.blocks.len();
+        let all: BTreeSet<usize> = (0..n).collect();
+        let mut dom = vec![all; n];
+        let entries = self.entries();
+        for &e in &entries {
+            dom[e] = [e].iter().cloned().collect();
+        }
+        let preds = self.preds();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in (0..n).filter(|i| !entries.contains(i)) {
+                let mut d: Option<BTreeSet<usize>> = None;
+                for &p in &preds[i] {
+                    d = Some(match d {
+                        None => dom[p].clone(),
+                        Some(d) => d.intersection(&dom[p]).cloned().collect(),
+                    });
+                }
+                let mut d = d.unwrap_or_else(|| dom[i].clone());
+                d.insert(i);
+                if d != dom[i] {
+                    dom[i] = d;
+                    changed = true;
+                }
+            }
+        }
+        dom
+    }
+
+    /// Find all natural loops, ordered by header. Back edges to the same
+    /// header are merged into one loop.
+    pub fn loops(&self) -> Vec<Loop> {
+        let dom = self.dominators();
+        let preds = self.preds();
+        let mut loops: Vec<Loop> = Vec::new();
+        for i in self.reachable() {
+            for &h in &self.blocks[i].succs {
+                if !dom[i].contains(&h) {
+                    continue;
+                }
+                // A back edge i -> h: the body is everything that can reach
+                // i without passing through h.
+                let mut body: BTreeSet<usize> = [h, i].iter().cloned().collect();
+                let mut stack = vec![i];
+                while let Some(x) = stack.pop() {
+                    if x == h {
+                        continue;
+                    }
+                    for &p in &preds[x] {
+                        if body.insert(p) {
+                            stack.push(p);
+                        }
+                    }
+                }
+                match loops.iter_mut().find(|l| l.header == h) {
+                    Some(l) => l.body.extend(body),
+                    None => loops.push(Loop { header: h, body }),
+                }
+            }
+        }
+        loops.sort_by_key(|l| l.header);
+        loops
+    }
+
+    /// Render the graph in Graphviz DOT format, with loop back edges in bold.
+    pub fn to_dot(&self, prog: &Program) -> String {
+        let dom = self.dominators();
+        let reachable = self.reachable();
+        let mut s = String::new();
+        writeln!(s, "digraph elfcode {{").unwrap();
+        writeln!(s, "    node [shape=box, fontname=monospace];").unwrap();
+        for (i, b) in self.blocks.iter().enumerate() {
+            let mut label = String::new();
+            for pc in b.start..b.end {
+                write!(label, "{}: {}\\l", pc, pseudo(prog.ip_reg, &prog.code, pc)).unwrap();
+            }
+            let color = if b.dynamic { ", color=red" } else { "" };
+            writeln!(s, "    b{} [label=\"{}\"{}];", i, label, color).unwrap();
+        }
+        if self.blocks.iter().any(|b| b.halts) {
+            writeln!(s, "    halt [shape=oval];").unwrap();
+        }
+        for (i, b) in self.blocks.iter().enumerate() {
+            for &j in &b.succs {
+                let style = if reachable.contains(&i) && dom[i].contains(&j) {
+                    " [style=bold]"
+                } else {
+                    ""
+                };
+                writeln!(s, "    b{} -> b{}{};", i, j, style).unwrap();
+            }
+            if b.halts {
+                writeln!(s, "    b{} -> halt;", i).unwrap();
+            }
+        }
+        writeln!(s, "}}").unwrap();
+        s
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn day19() -> Program {
+        include_str!("../../input/input19.txt").parse().unwrap()
+    }
+
+    fn day21() -> Program {
+        include_str!("../../input/input21.txt").parse().unwrap()
+    }
+
+    fn starts(cfg: &Cfg, blocks: &BTreeSet<usize>) -> Vec<usize> {
+        blocks.iter().map(|i| cfg.blocks[*i].start).collect()
+    }
+
+    #[test]
+    fn day19_blocks() {
+        let cfg = Cfg::new(&day19());
+        let b0 = &cfg.blocks[0];
+        assert_eq!((b0.start, b0.end), (0, 1));
+        assert_eq!(cfg.blocks[b0.succs[0]].start, 17);
+        // `mulr 5 5 5` jumps out of the program.
+        let b16 = &cfg.blocks[cfg.block_containing(16).unwrap()];
+        assert!(b16.halts);
+        assert!(b16.succs.is_empty());
+        // `addr 5 0 5` depends on r0.
+        assert!(cfg.blocks[cfg.block_containing(25).unwrap()].dynamic);
+    }
+
+    #[test]
+    fn day19_loops() {
+        let cfg = Cfg::new(&day19());
+        let loops = cfg.loops();
+        assert_eq!(loops.len(), 2);
+        // The outer loop over r1, and the inner loop over r2.
+        assert_eq!(cfg.blocks[loops[0].header].start, 2);
+        assert_eq!(
+            starts(&cfg, &loops[0].body),
+            vec![2, 3, 6, 7, 8, 11, 12, 15]
+        );
+        assert_eq!(cfg.blocks[loops[1].header].start, 3);
+        assert_eq!(starts(&cfg, &loops[1].body), vec![3, 6, 7, 8, 11]);
+    }
+
+    #[test]
+    fn day21_loops() {
+        let cfg = Cfg::new(&day21());
+        let headers: Vec<usize> = cfg
+            .loops()
+            .iter()
+            .map(|l| cfg.blocks[l.header].start)
+            .collect();
+        assert_eq!(headers, vec![1, 6, 8, 18]);
+    }
+
+    #[test]
+    fn dot() {
+        let p = day21();
+        let dot = Cfg::new(&p).to_dot(&p);
+        assert!(dot.starts_with("digraph elfcode {\n"));
+        assert!(dot.contains("halt [shape=oval];"));
+        assert!(dot.contains("[style=bold]"));
+        assert!(dot.contains("28: r2 = r5 == r0\\l"));
+    }
+}
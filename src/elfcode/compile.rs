@@ -0,0 +1,601 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compile ElfCode into a specialized instruction stream.
+//!
+//! Compared to interpreting `Inst`s:
+//!
+//! * Reads of the IP register are replaced by the (known) address of the
+//!   instruction, so operands are register-or-literal fixed at compile time.
+//! * Writes to the IP register become direct gotos or conditional branches,
+//!   and the IP register itself is only updated when the program stops.
+//! * A comparison followed by a conditional skip on its result is fused into
+//!   one compare-and-branch op.
+//! * Arithmetic follows the program's `Word`, and faults wherever the
+//!   interpreter would.
+//!
+//! The compiled code is indexed by the same addresses as the source, so
+//! jumps can still land on any instruction.
+
+use super::disasm::{flow_in, Flow};
+use super::{Fault, FaultKind, Inst, Opcode, Operand, Program, Reg, Word};
+
+/// A pre-decoded operation. `r*` fields are register numbers and `i*` are
+/// literal values.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Op {
+    Addr {
+        ra: usize,
+        rb: usize,
+        c: usize,
+    },
+    Addi {
+        ra: usize,
+        ib: Reg,
+        c: usize,
+    },
+    Mulr {
+        ra: usize,
+        rb: usize,
+        c: usize,
+    },
+    Muli {
+        ra: usize,
+        ib: Reg,
+        c: usize,
+    },
+    Banr {
+        ra: usize,
+        rb: usize,
+        c: usize,
+    },
+    Bani {
+        ra: usize,
+        ib: Reg,
+        c: usize,
+    },
+    Borr {
+        ra: usize,
+        rb: usize,
+        c: usize,
+    },
+    Bori {
+        ra: usize,
+        ib: Reg,
+        c: usize,
+    },
+    Setr {
+        ra: usize,
+        c: usize,
+    },
+    Seti {
+        ia: Reg,
+        c: usize,
+    },
+    Gtir {
+        ia: Reg,
+        rb: usize,
+        c: usize,
+    },
+    Gtri {
+        ra: usize,
+        ib: Reg,
+        c: usize,
+    },
+    Gtrr {
+        ra: usize,
+        rb: usize,
+        c: usize,
+    },
+    Eqir {
+        ia: Reg,
+        rb: usize,
+        c: usize,
+    },
+    Eqri {
+        ra: usize,
+        ib: Reg,
+        c: usize,
+    },
+    Eqrr {
+        ra: usize,
+        rb: usize,
+        c: usize,
+    },
+    /// Continue at this address.
+    Goto(usize),
+    /// Continue at `target` if register `reg` is 1, or at the next
+    /// instruction if it's 0. Any other value is added to the IP, as the
+    /// original `addr` would.
+    Branch {
+        reg: usize,
+        target: usize,
+    },
+    // Fused compare-and-branch ops, for a comparison at `pc` followed by a
+    // conditional skip on its result: store the result into `c`, then
+    // continue at `pc + 3` if it's 1, or `pc + 2` if it's 0.
+    GtirBranch {
+        ia: Reg,
+        rb: usize,
+        c: usize,
+        pc: usize,
+    },
+    GtriBranch {
+        ra: usize,
+        ib: Reg,
+        c: usize,
+        pc: usize,
+    },
+    GtrrBranch {
+        ra: usize,
+        rb: usize,
+        c: usize,
+        pc: usize,
+    },
+    EqirBranch {
+        ia: Reg,
+        rb: usize,
+        c: usize,
+        pc: usize,
+    },
+    EqriBranch {
+        ra: usize,
+        ib: Reg,
+        c: usize,
+        pc: usize,
+    },
+    EqrrBranch {
+        ra: usize,
+        rb: usize,
+        c: usize,
+        pc: usize,
+    },
+    /// An instruction run by the ordinary interpreter, such as a computed
+    /// jump.
+    Dynamic(Inst),
+}
+
+/// Replace reads of the IP register in `inst` at address `pc` by literals.
+///
+/// Returns the instruction unchanged if it doesn't read the IP, or None if
+/// it can't be expressed without it.
+fn fold_ip(word: Word, ip_reg: Option<usize>, inst: &Inst, pc: usize) -> Option<Inst> {
+    use self::Opcode::*;
+    let ip_reg = match ip_reg {
+        Some(r) if inst.reads().contains(&r) => r,
+        _ => return Some(*inst),
+    };
+    let (oa, ob) = inst.opcode.operands();
+    let a_ip = oa == Operand::Reg && inst.a == ip_reg;
+    let b_ip = ob == Operand::Reg && inst.b == ip_reg;
+    let mut out = *inst;
+    if (a_ip || oa != Operand::Reg) && (b_ip || ob != Operand::Reg) {
+        // Everything is known, so this is just a constant, unless computing
+        // it faults.
+        let mut reg = vec![0; ip_reg + 1];
+        reg[ip_reg] = pc;
+        out.opcode = Seti;
+        out.a = inst.opcode.eval_in(word, &reg, inst.a, inst.b).ok()?;
+        return Some(out);
+    }
+    let swapped = |op| Inst {
+        opcode: op,
+        a: inst.b,
+        b: inst.a,
+        c: inst.c,
+    };
+    // Exactly one operand is the IP, and the other is a register.
+    match (inst.opcode, a_ip) {
+        (Addr, true) => out = swapped(Addi),
+        (Addr, false) => out.opcode = Addi,
+        (Mulr, true) => out = swapped(Muli),
+        (Mulr, false) => out.opcode = Muli,
+        (Banr, true) => out = swapped(Bani),
+        (Banr, false) => out.opcode = Bani,
+        (Borr, true) => out = swapped(Bori),
+        (Borr, false) => out.opcode = Bori,
+        (Gtrr, true) => out.opcode = Gtir,
+        (Gtrr, false) => out.opcode = Gtri,
+        (Eqrr, true) => out.opcode = Eqir,
+        (Eqrr, false) => out.opcode = Eqri,
+        _ => return None,
+    }
+    if a_ip && !matches!(inst.opcode, Addr | Mulr | Banr | Borr) {
+        out.a = pc;
+    } else {
+        out.b = pc;
+    }
+    Some(out)
+}
+
+fn decode(inst: &Inst) -> Op {
+    let (a, b, c) = (inst.a, inst.b, inst.c);
+    match inst.opcode {
+        Opcode::Addr => Op::Addr { ra: a, rb: b, c },
+        Opcode::Addi => Op::Addi { ra: a, ib: b, c },
+        Opcode::Mulr => Op::Mulr { ra: a, rb: b, c },
+        Opcode::Muli => Op::Muli { ra: a, ib: b, c },
+        Opcode::Banr => Op::Banr { ra: a, rb: b, c },
+        Opcode::Bani => Op::Bani { ra: a, ib: b, c },
+        Opcode::Borr => Op::Borr { ra: a, rb: b, c },
+        Opcode::Bori => Op::Bori { ra: a, ib: b, c },
+        Opcode::Setr => Op::Setr { ra: a, c },
+        Opcode::Seti => Op::Seti { ia: a, c },
+        Opcode::Gtir => Op::Gtir { ia: a, rb: b, c },
+        Opcode::Gtri => Op::Gtri { ra: a, ib: b, c },
+        Opcode::Gtrr => Op::Gtrr { ra: a, rb: b, c },
+        Opcode::Eqir => Op::Eqir { ia: a, rb: b, c },
+        Opcode::Eqri => Op::Eqri { ra: a, ib: b, c },
+        Opcode::Eqrr => Op::Eqrr { ra: a, rb: b, c },
+    }
+}
+
+/// Fuse a comparison op at `pc` with the following branch.
+fn fuse(op: Op, pc: usize) -> Option<Op> {
+    Some(match op {
+        Op::Gtir { ia, rb, c } => Op::GtirBranch { ia, rb, c, pc },
+        Op::Gtri { ra, ib, c } => Op::GtriBranch { ra, ib, c, pc },
+        Op::Gtrr { ra, rb, c } => Op::GtrrBranch { ra, rb, c, pc },
+        Op::Eqir { ia, rb, c } => Op::EqirBranch { ia, rb, c, pc },
+        Op::Eqri { ra, ib, c } => Op::EqriBranch { ra, ib, c, pc },
+        Op::Eqrr { ra, rb, c } => Op::EqrrBranch { ra, rb, c, pc },
+        _ => return None,
+    })
+}
+
+#[inline]
+fn get(r: &[Reg], x: usize) -> Result<Reg, FaultKind> {
+    r.get(x).cloned().ok_or(FaultKind::BadRegister(x))
+}
+
+/// Check that `v`, the result of `opcode` on `x` and `y`, fits the word.
+/// Otherwise, or if it overflowed, work it out again the same way as
+/// `Opcode::eval_in`, to trap, wrap or saturate.
+#[inline]
+fn fit(word: Word, v: Option<Reg>, opcode: Opcode, x: Reg, y: Reg) -> Result<Reg, FaultKind> {
+    match v {
+        Some(v) if v <= word.max() => Ok(v),
+        _ => opcode.eval_in(word, &[x, y], 0, 1),
+    }
+}
+
+#[inline]
+fn add(word: Word, x: Reg, y: Reg) -> Result<Reg, FaultKind> {
+    fit(word, x.checked_add(y), Opcode::Addr, x, y)
+}
+
+#[inline]
+fn mul(word: Word, x: Reg, y: Reg) -> Result<Reg, FaultKind> {
+    fit(word, x.checked_mul(y), Opcode::Mulr, x, y)
+}
+
+#[inline]
+fn and(word: Word, x: Reg, y: Reg) -> Result<Reg, FaultKind> {
+    fit(word, Some(x & y), Opcode::Banr, x, y)
+}
+
+#[inline]
+fn or(word: Word, x: Reg, y: Reg) -> Result<Reg, FaultKind> {
+    fit(word, Some(x | y), Opcode::Borr, x, y)
+}
+
+#[inline]
+fn set(word: Word, x: Reg) -> Result<Reg, FaultKind> {
+    fit(word, Some(x), Opcode::Setr, x, 0)
+}
+
+/// Evaluate an arithmetic or comparison op like `Opcode::eval_in`,
+/// returning the value to store and the register to store it in.
+#[inline]
+fn arith(op: &Op, word: Word, r: &[Reg]) -> Result<(Reg, usize), FaultKind> {
+    Ok(match *op {
+        Op::Addr { ra, rb, c } => (add(word, get(r, ra)?, get(r, rb)?)?, c),
+        Op::Addi { ra, ib, c } => (add(word, get(r, ra)?, ib)?, c),
+        Op::Mulr { ra, rb, c } => (mul(word, get(r, ra)?, get(r, rb)?)?, c),
+        Op::Muli { ra, ib, c } => (mul(word, get(r, ra)?, ib)?, c),
+        Op::Banr { ra, rb, c } => (and(word, get(r, ra)?, get(r, rb)?)?, c),
+        Op::Bani { ra, ib, c } => (and(word, get(r, ra)?, ib)?, c),
+        Op::Borr { ra, rb, c } => (or(word, get(r, ra)?, get(r, rb)?)?, c),
+        Op::Bori { ra, ib, c } => (or(word, get(r, ra)?, ib)?, c),
+        Op::Setr { ra, c } => (set(word, get(r, ra)?)?, c),
+        Op::Seti { ia, c } => (set(word, ia)?, c),
+        Op::Gtir { ia, rb, c } => ((ia > get(r, rb)?) as Reg, c),
+        Op::Gtri { ra, ib, c } => ((get(r, ra)? > ib) as Reg, c),
+        Op::Gtrr { ra, rb, c } => ((get(r, ra)? > get(r, rb)?) as Reg, c),
+        Op::Eqir { ia, rb, c } => ((ia == get(r, rb)?) as Reg, c),
+        Op::Eqri { ra, ib, c } => ((get(r, ra)? == ib) as Reg, c),
+        Op::Eqrr { ra, rb, c } => ((get(r, ra)? == get(r, rb)?) as Reg, c),
+        _ => unreachable!("{:?} is not arithmetic", op),
+    })
+}
+
+#[inline]
+fn store(r: &mut [Reg], c: usize, v: Reg) -> Result<(), FaultKind> {
+    *r.get_mut(c).ok_or(FaultKind::BadRegister(c))? = v;
+    Ok(())
+}
+
+/// Store the result of a fused comparison and return the next IP.
+#[inline]
+fn branch(r: &mut [Reg], c: usize, pc: usize, v: bool) -> Result<usize, FaultKind> {
+    store(r, c, v as Reg)?;
+    Ok(pc + 2 + v as usize)
+}
+
+/// A compiled program.
+#[derive(Debug, Clone)]
+pub struct Compiled {
+    ip_reg: Option<usize>,
+    word: Word,
+    ops: Vec<Op>,
+}
+
+impl Compiled {
+    pub fn new(prog: &Program) -> Compiled {
+        let (ip_reg, word) = (prog.ip_reg, prog.word);
+        let code = &prog.code;
+        let mut ops: Vec<Op> = (0..code.len())
+            .map(|pc| match flow_in(word, ip_reg, code, pc) {
+                Flow::Next => match fold_ip(word, ip_reg, &code[pc], pc) {
+                    Some(inst) => decode(&inst),
+                    None => Op::Dynamic(code[pc]),
+                },
+                // A saturated target doesn't say what the IP register
+                // should hold, so leave that to the interpreter.
+                Flow::Goto(t) if t != usize::MAX => Op::Goto(t),
+                Flow::Branch { reg, target } => Op::Branch { reg, target },
+                Flow::Goto(_) | Flow::Dynamic => Op::Dynamic(code[pc]),
+            })
+            .collect();
+        for pc in 0..ops.len().saturating_sub(1) {
+            if let Op::Branch { reg, .. } = ops[pc + 1] {
+                if code[pc].c == reg {
+                    if let Some(fused) = fuse(ops[pc], pc) {
+                        ops[pc] = fused;
+                    }
+                }
+            }
+        }
+        Compiled { ip_reg, word, ops }
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// Run from the program's current state until it halts; then return
+    /// the contents of register 0. Panics if the program faults.
+    pub fn eval(&self, prog: &mut Program) -> Reg {
+        self.run(prog).unwrap_or_else(|fault| panic!("{}", fault))
+    }
+
+    /// Run from the program's current state until it halts and return
+    /// register 0, or until it faults and return the fault.
+    ///
+    /// The program is left in the same state the interpreter would leave
+    /// it.
+    pub fn run(&self, prog: &mut Program) -> Result<Reg, Fault> {
+        let mut ip = prog.ip;
+        let code = &prog.code;
+        let r = prog.reg.as_mut_slice();
+        let ops = self.ops.as_slice();
+        let mut ran = false;
+        while let Some(op) = ops.get(ip) {
+            ran = true;
+            match self.exec(op, ip, code, r) {
+                Ok(next) => ip = next,
+                Err(kind) => {
+                    if let Some(ip_reg) = self.ip_reg {
+                        r[ip_reg] = ip;
+                    }
+                    let fault = Fault {
+                        kind,
+                        ip,
+                        inst: code[ip],
+                        reg: r.to_vec(),
+                    };
+                    prog.ip = ip;
+                    prog.fault = Some(fault.clone());
+                    return Err(fault);
+                }
+            }
+        }
+        if let Some(ip_reg) = self.ip_reg {
+            // After every instruction the IP register holds one less than
+            // the next IP. The only way to reach `usize::MAX` is through
+            // the interpreter, which has already set it exactly.
+            if ran && ip != usize::MAX {
+                r[ip_reg] = ip - 1;
+            }
+        }
+        prog.ip = ip;
+        Ok(prog.reg[0])
+    }
+
+    /// Run one op at `ip` and return the next IP.
+    #[inline]
+    fn exec(&self, op: &Op, ip: usize, code: &[Inst], r: &mut [Reg]) -> Result<usize, FaultKind> {
+        let word = self.word;
+        Ok(match *op {
+            Op::Goto(t) => t,
+            Op::Branch { reg, target } => match r.get(reg) {
+                Some(0) => ip + 1,
+                Some(1) => target,
+                _ => self.interpret(&code[ip], ip, r)?,
+            },
+            Op::GtirBranch { ia, rb, c, pc } => branch(r, c, pc, ia > get(r, rb)?)?,
+            Op::GtriBranch { ra, ib, c, pc } => branch(r, c, pc, get(r, ra)? > ib)?,
+            Op::GtrrBranch { ra, rb, c, pc } => branch(r, c, pc, get(r, ra)? > get(r, rb)?)?,
+            Op::EqirBranch { ia, rb, c, pc } => branch(r, c, pc, ia == get(r, rb)?)?,
+            Op::EqriBranch { ra, ib, c, pc } => branch(r, c, pc, get(r, ra)? == ib)?,
+            Op::EqrrBranch { ra, rb, c, pc } => branch(r, c, pc, get(r, ra)? == get(r, rb)?)?,
+            Op::Dynamic(ref inst) => self.interpret(inst, ip, r)?,
+            ref op => {
+                let (v, c) = arith(op, word, r)?;
+                store(r, c, v)?;
+                ip + 1
+            }
+        })
+    }
+
+    /// Run one instruction the way `Program::step` does, and return the
+    /// next IP.
+    fn interpret(&self, inst: &Inst, ip: usize, r: &mut [Reg]) -> Result<usize, FaultKind> {
+        if let Some(ip_reg) = self.ip_reg {
+            r[ip_reg] = ip;
+        }
+        inst.exec(self.word, r)?;
+        Ok(match self.ip_reg {
+            Some(ip_reg) => r[ip_reg].saturating_add(1),
+            None => ip + 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::elfcode::Overflow;
+
+    fn day19() -> Program {
+        include_str!("../../input/input19.txt").parse().unwrap()
+    }
+
+    fn day21() -> Program {
+        include_str!("../../input/input21.txt").parse().unwrap()
+    }
+
+    #[test]
+    fn folding() {
+        let p = day19();
+        let c = Compiled::new(&p);
+        // `mulr 5 3 3` at 19: r3 = 19 * r3.
+        assert_eq!(
+            c.ops()[19],
+            Op::Muli {
+                ra: 3,
+                ib: 19,
+                c: 3
+            }
+        );
+        // `setr 5 3 4` at 27: r4 = 27.
+        assert_eq!(c.ops()[27], Op::Seti { ia: 27, c: 4 });
+        // `mulr 4 5 4` at 28: r4 = r4 * 28.
+        assert_eq!(
+            c.ops()[28],
+            Op::Muli {
+                ra: 4,
+                ib: 28,
+                c: 4
+            }
+        );
+        assert_eq!(c.ops()[0], Op::Goto(17));
+        assert_eq!(c.ops()[25], Op::Dynamic(p.code[25]));
+    }
+
+    #[test]
+    fn fold_comparisons() {
+        let fold = |s: &str| {
+            fold_ip(Word::default(), Some(4), &s.parse().unwrap(), 9).map(|i| i.to_string())
+        };
+        assert_eq!(fold("gtrr 4 1 3").unwrap(), "gtir 9 1 3");
+        assert_eq!(fold("eqrr 1 4 3").unwrap(), "eqri 1 9 3");
+        assert_eq!(fold("addr 4 2 2").unwrap(), "addi 2 9 2");
+        assert_eq!(fold("muli 4 18446744073709551615 2"), None);
+    }
+
+    #[test]
+    fn fused() {
+        let c = Compiled::new(&day21());
+        assert_eq!(
+            c.ops()[20],
+            Op::GtrrBranch {
+                ra: 1,
+                rb: 3,
+                c: 1,
+                pc: 20
+            }
+        );
+        // The branch itself is still there in case something jumps to it.
+        assert_eq!(c.ops()[21], Op::Branch { reg: 1, target: 23 });
+    }
+
+    #[test]
+    fn same_as_interpreter() {
+        let mut slow = day19();
+        let mut fast = day19();
+        assert_eq!(Compiled::new(&fast).eval(&mut fast), 1302);
+        slow.eval();
+        assert_eq!(slow.reg, fast.reg);
+        assert_eq!(slow.ip, fast.ip);
+
+        let mut slow = day21();
+        let mut fast = day21();
+        slow.reg[0] = 202209;
+        fast.reg[0] = 202209;
+        Compiled::new(&fast).eval(&mut fast);
+        slow.eval();
+        assert_eq!(slow.reg, fast.reg);
+        assert_eq!(slow.ip, fast.ip);
+    }
+
+    /// Run a program both ways from the start and check they agree.
+    fn differential(text: &str, word: Word) -> Result<Reg, Fault> {
+        let mut slow: Program = text.parse().unwrap();
+        slow.word = word;
+        let mut fast = slow.clone();
+        let expected = slow.run();
+        assert_eq!(Compiled::new(&fast).run(&mut fast), expected);
+        assert_eq!(slow.reg, fast.reg);
+        assert_eq!(slow.ip, fast.ip);
+        expected
+    }
+
+    #[test]
+    fn jump_between_compare_and_branch() {
+        let text = "#ip 4\nseti 3 0 1\nseti 2 0 4\neqrr 0 0 1\naddr 1 4 4\nseti 11 0 0\n\
+                    seti 99 0 4\nseti 0 0 0\nseti 22 0 0\n";
+        assert_eq!(differential(text, Word::default()), Ok(22));
+    }
+
+    #[test]
+    fn edge_cases() {
+        assert_eq!(differential("#ip 0\n", Word::default()), Ok(0));
+        // Jumps to the very end of the address space.
+        assert_eq!(
+            differential("#ip 0\nseti 18446744073709551615 0 0\n", Word::default()),
+            Ok(Reg::MAX)
+        );
+        assert_eq!(
+            differential(
+                "#ip 1\nseti 18446744073709551614 0 0\naddr 0 1 1\n",
+                Word::default()
+            ),
+            Ok(Reg::MAX - 1)
+        );
+    }
+
+    #[test]
+    fn faults_and_word_sizes() {
+        let text = "#ip 5\nseti 1 0 1\nmulr 1 1 2\naddr 1 2 1\ngtri 1 100 3\naddr 3 5 5\n\
+                    seti 0 0 5\nsetr 1 0 0\nmuli 0 3 0\n";
+        assert_eq!(differential(text, Word::default()), Ok(3 * 1806));
+        let fault = differential(text, Word::new(8, Overflow::Trap)).unwrap_err();
+        assert_eq!((fault.kind, fault.ip), (FaultKind::Overflow, 1));
+        assert!(differential(text, Word::new(8, Overflow::Wrap)).is_ok());
+        assert!(differential(text, Word::new(12, Overflow::Saturate)).is_ok());
+        let fault = differential("#ip 5\naddr 1 9 2\n", Word::default()).unwrap_err();
+        assert_eq!(fault.kind, FaultKind::BadRegister(9));
+    }
+}
@@ -0,0 +1,581 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Debug an ElfCode program: breakpoints, watchpoints and tracing.
+//!
+//! The debugger can be driven directly through its methods, or by text
+//! commands (see `Debugger::command`) from a script or an interactive
+//! session.
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::replay::Snapshot;
+use super::{Fault, Inst, Program, Reg};
+
+/// A comparison between a register and a constant.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Cmp {
+    fn test(self, x: Reg, y: Reg) -> bool {
+        match self {
+            Cmp::Eq => x == y,
+            Cmp::Ne => x != y,
+            Cmp::Lt => x < y,
+            Cmp::Le => x <= y,
+            Cmp::Gt => x > y,
+            Cmp::Ge => x >= y,
+        }
+    }
+}
+
+impl FromStr for Cmp {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Cmp, ()> {
+        Ok(match s {
+            "==" => Cmp::Eq,
+            "!=" => Cmp::Ne,
+            "<" => Cmp::Lt,
+            "<=" => Cmp::Le,
+            ">" => Cmp::Gt,
+            ">=" => Cmp::Ge,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl fmt::Display for Cmp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Cmp::Eq => "==",
+            Cmp::Ne => "!=",
+            Cmp::Lt => "<",
+            Cmp::Le => "<=",
+            Cmp::Gt => ">",
+            Cmp::Ge => ">=",
+        })
+    }
+}
+
+/// A condition on a register value, like `r5 == 0`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cond {
+    pub reg: usize,
+    pub cmp: Cmp,
+    pub value: Reg,
+}
+
+impl Cond {
+    /// True if the condition holds; a register that doesn't exist never
+    /// matches.
+    pub fn test(&self, reg: &[Reg]) -> bool {
+        reg.get(self.reg)
+            .is_some_and(|&x| self.cmp.test(x, self.value))
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "r{} {} {}", self.reg, self.cmp, self.value)
+    }
+}
+
+/// Stop before executing the instruction at `ip`, optionally only if a
+/// condition holds.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub ip: usize,
+    pub cond: Option<Cond>,
+}
+
+/// Why the debugger stopped running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    /// Ran the requested number of steps.
+    Stepped,
+    /// About to execute the instruction at a breakpoint.
+    Breakpoint(usize),
+    /// A watched register changed.
+    Watchpoint { reg: usize, old: Reg, new: Reg },
+    /// The IP left the program.
+    Halted,
+    /// The instruction at the IP can't run.
+    Fault(Fault),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Stepped => write!(f, "stepped"),
+            Stop::Breakpoint(ip) => write!(f, "breakpoint at {}", ip),
+            Stop::Watchpoint { reg, old, new } => write!(f, "r{} changed {} -> {}", reg, old, new),
+            Stop::Halted => write!(f, "halted"),
+            Stop::Fault(fault) => write!(f, "fault: {}", fault),
+        }
+    }
+}
+
+/// One executed instruction, as recorded in the trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub ip: usize,
+    pub inst: Inst,
+    pub before: Vec<Reg>,
+    pub after: Vec<Reg>,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ip={:<3} {:?} {:<16} {:?}",
+            self.ip,
+            self.before,
+            self.inst.to_string(),
+            self.after
+        )
+    }
+}
+
+/// Runs a program under control of breakpoints and watchpoints.
+pub struct Debugger {
+    pub prog: Program,
+    breakpoints: Vec<Breakpoint>,
+    /// Registers to watch for changes.
+    watches: Vec<usize>,
+    /// If tracing is on, all instructions executed since it was turned on.
+    trace: Option<Vec<TraceEntry>>,
+    /// Total instructions executed.
+    cycles: usize,
+    /// Saved states to rewind to, with the cycle count when they were
+    /// saved.
+    checkpoints: Vec<(Snapshot, usize)>,
+}
+
+impl Debugger {
+    pub fn new(prog: Program) -> Debugger {
+        Debugger {
+            prog,
+            breakpoints: Vec::new(),
+            watches: Vec::new(),
+            trace: None,
+            cycles: 0,
+            checkpoints: Vec::new(),
+        }
+    }
+
+    /// Number of instructions executed so far.
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    pub fn add_breakpoint(&mut self, ip: usize, cond: Option<Cond>) {
+        self.breakpoints.push(Breakpoint { ip, cond });
+    }
+
+    /// Remove all breakpoints at `ip`; return true if there were any.
+    pub fn remove_breakpoint(&mut self, ip: usize) -> bool {
+        let l = self.breakpoints.len();
+        self.breakpoints.retain(|b| b.ip != ip);
+        self.breakpoints.len() != l
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn watch(&mut self, reg: usize) {
+        assert!(reg < self.prog.reg.len(), "no register {}", reg);
+        if !self.watches.contains(&reg) {
+            self.watches.push(reg);
+        }
+    }
+
+    pub fn unwatch(&mut self, reg: usize) -> bool {
+        let l = self.watches.len();
+        self.watches.retain(|r| *r != reg);
+        self.watches.len() != l
+    }
+
+    /// Turn tracing on or off. Turning it on starts a fresh trace.
+    pub fn set_trace(&mut self, on: bool) {
+        self.trace = if on { Some(Vec::new()) } else { None };
+    }
+
+    /// Instructions executed since tracing was turned on.
+    pub fn trace(&self) -> &[TraceEntry] {
+        self.trace.as_ref().map_or(&[], |t| t.as_slice())
+    }
+
+    /// True if a breakpoint matches the current state.
+    fn at_breakpoint(&self) -> bool {
+        let ip = self.prog.ip;
+        let reg = &self.prog.reg;
+        self.breakpoints
+            .iter()
+            .any(|b| b.ip == ip && b.cond.is_none_or(|c| c.test(reg)))
+    }
+
+    /// Execute exactly one instruction, ignoring any breakpoint at the
+    /// current IP, but reporting watched registers that change.
+    fn step_one(&mut self) -> Option<Stop> {
+        if let Some(fault) = &self.prog.fault {
+            return Some(Stop::Fault(fault.clone()));
+        } else if self.prog.is_halted() {
+            return Some(Stop::Halted);
+        }
+        let ip = self.prog.ip;
+        let inst = self.prog.code[ip];
+        let mut before = self.prog.reg.clone();
+        if let Some(ip_reg) = self.prog.ip_reg {
+            // Show the IP as the instruction will see it.
+            before[ip_reg] = ip;
+        }
+        self.prog.step();
+        if let Some(fault) = &self.prog.fault {
+            return Some(Stop::Fault(fault.clone()));
+        }
+        self.cycles += 1;
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(TraceEntry {
+                ip,
+                inst,
+                before: before.clone(),
+                after: self.prog.reg.clone(),
+            });
+        }
+        for &reg in &self.watches {
+            if before[reg] != self.prog.reg[reg] {
+                return Some(Stop::Watchpoint {
+                    reg,
+                    old: before[reg],
+                    new: self.prog.reg[reg],
+                });
+            }
+        }
+        if self.prog.is_halted() {
+            Some(Stop::Halted)
+        } else {
+            None
+        }
+    }
+
+    /// Execute up to `n` instructions, stopping early at a watchpoint or
+    /// breakpoint.
+    ///
+    /// The instruction at the current IP is always executed, even if there's
+    /// a breakpoint on it, so that repeated steps make progress.
+    pub fn step(&mut self, n: usize) -> Stop {
+        for i in 0..n {
+            if i > 0 && self.at_breakpoint() {
+                return Stop::Breakpoint(self.prog.ip);
+            }
+            if let Some(stop) = self.step_one() {
+                return stop;
+            }
+        }
+        Stop::Stepped
+    }
+
+    /// Run until a breakpoint or watchpoint fires, or the program halts.
+    ///
+    /// Like `step`, this first moves past any breakpoint at the current IP.
+    pub fn cont(&mut self) -> Stop {
+        loop {
+            if let Some(stop) = self.step_one() {
+                return stop;
+            }
+            if self.at_breakpoint() {
+                return Stop::Breakpoint(self.prog.ip);
+            }
+        }
+    }
+
+    /// Describe the current state.
+    pub fn status(&self) -> String {
+        if let Some(fault) = &self.prog.fault {
+            return format!("ip={} {:?} fault: {}", self.prog.ip, self.prog.reg, fault);
+        }
+        match self.prog.current() {
+            Some(inst) => format!("ip={} {:?} next: {}", self.prog.ip, self.prog.reg, inst),
+            None => format!("ip={} {:?} halted", self.prog.ip, self.prog.reg),
+        }
+    }
+
+    /// Execute one text command, returning the text to show to the user.
+    ///
+    /// Commands are:
+    ///
+    /// * `break IP [if rN CMP VALUE]`: add a breakpoint, where `CMP` is one
+    ///   of `==`, `!=`, `<`, `<=`, `>`, `>=`.
+    /// * `delete IP`: remove breakpoints at IP.
+    /// * `watch N` / `unwatch N`: stop when register N changes.
+    /// * `step [N]`: execute N instructions, default 1.
+    /// * `continue`: run until something stops execution.
+    /// * `set rN VALUE`: change a register.
+    /// * `regs`: show the IP and registers.
+    /// * `trace on|off|show`: record and show executed instructions.
+    /// * `checkpoint`: save the machine state, and print its number.
+    /// * `rewind N`: go back to checkpoint N.
+    pub fn command(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => Ok(String::new()),
+            ["break", ip] => {
+                let ip = parse_num(ip)?;
+                self.add_breakpoint(ip, None);
+                Ok(format!("breakpoint at {}", ip))
+            }
+            ["break", ip, "if", r, cmp, value] => {
+                let ip = parse_num(ip)?;
+                let cond = Cond {
+                    reg: self.parse_reg(r)?,
+                    cmp: cmp
+                        .parse()
+                        .map_err(|_| format!("unknown comparison {:?}", cmp))?,
+                    value: parse_num(value)?,
+                };
+                self.add_breakpoint(ip, Some(cond));
+                Ok(format!("breakpoint at {} if {}", ip, cond))
+            }
+            ["delete", ip] => {
+                let ip = parse_num(ip)?;
+                if self.remove_breakpoint(ip) {
+                    Ok(format!("deleted breakpoint at {}", ip))
+                } else {
+                    Err(format!("no breakpoint at {}", ip))
+                }
+            }
+            ["watch", r] => {
+                let r = self.parse_reg(r)?;
+                self.watch(r);
+                Ok(format!("watching r{}", r))
+            }
+            ["unwatch", r] => {
+                let r = self.parse_reg(r)?;
+                if self.unwatch(r) {
+                    Ok(format!("stopped watching r{}", r))
+                } else {
+                    Err(format!("not watching r{}", r))
+                }
+            }
+            ["step"] | ["s"] => {
+                let stop = self.step(1);
+                Ok(self.stopped(&stop))
+            }
+            ["step", n] | ["s", n] => {
+                let stop = self.step(parse_num(n)?);
+                Ok(self.stopped(&stop))
+            }
+            ["continue"] | ["c"] => {
+                let stop = self.cont();
+                Ok(self.stopped(&stop))
+            }
+            ["set", r, value] => {
+                let r = self.parse_reg(r)?;
+                self.prog.reg[r] = parse_num(value)?;
+                Ok(self.status())
+            }
+            ["regs"] => Ok(self.status()),
+            ["trace", "on"] => {
+                self.set_trace(true);
+                Ok("tracing on".to_owned())
+            }
+            ["trace", "off"] => {
+                self.set_trace(false);
+                Ok("tracing off".to_owned())
+            }
+            ["trace", "show"] => Ok(self
+                .trace()
+                .iter()
+                .map(TraceEntry::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")),
+            ["checkpoint"] => {
+                self.checkpoints.push((self.prog.snapshot(), self.cycles));
+                Ok(format!(
+                    "checkpoint {} at cycle {}",
+                    self.checkpoints.len() - 1,
+                    self.cycles
+                ))
+            }
+            ["rewind", n] => {
+                let n = parse_num(n)?;
+                let (snapshot, cycles) = self
+                    .checkpoints
+                    .get(n)
+                    .ok_or_else(|| format!("no checkpoint {}", n))?;
+                self.prog.restore(snapshot);
+                self.cycles = *cycles;
+                Ok(self.status())
+            }
+            _ => Err(format!("unknown command {:?}", line.trim())),
+        }
+    }
+
+    fn stopped(&self, stop: &Stop) -> String {
+        format!("{}\n{}", stop, self.status())
+    }
+
+    /// Parse a register name like `r5` or `5`.
+    fn parse_reg(&self, s: &str) -> Result<usize, String> {
+        let r = parse_num(s.strip_prefix('r').unwrap_or(s))?;
+        if r < self.prog.reg.len() {
+            Ok(r)
+        } else {
+            Err(format!("no register {}", s))
+        }
+    }
+}
+
+fn parse_num(s: &str) -> Result<usize, String> {
+    s.parse()
+        .map_err(|_| format!("expected a number, not {:?}", s))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::elfcode::FaultKind;
+
+    fn day21() -> Debugger {
+        Debugger::new(include_str!("../../input/input21.txt").parse().unwrap())
+    }
+
+    #[test]
+    fn breakpoint() {
+        let mut d = day21();
+        d.add_breakpoint(28, None);
+        assert_eq!(d.cont(), Stop::Breakpoint(28));
+        assert_eq!(d.prog.reg[5], 202209);
+        // Continuing moves past the breakpoint, and then comes back to it.
+        assert_eq!(d.cont(), Stop::Breakpoint(28));
+        assert_ne!(d.prog.reg[5], 202209);
+    }
+
+    #[test]
+    fn conditional_breakpoint() {
+        let mut d = day21();
+        d.add_breakpoint(
+            17,
+            Some(Cond {
+                reg: 3,
+                cmp: Cmp::Lt,
+                value: 1000,
+            }),
+        );
+        assert_eq!(d.cont(), Stop::Breakpoint(17));
+        assert!(d.prog.reg[3] < 1000);
+
+        let missing = Cond {
+            reg: 9,
+            cmp: Cmp::Ge,
+            value: 0,
+        };
+        assert!(!missing.test(&d.prog.reg));
+    }
+
+    #[test]
+    fn watchpoint() {
+        let mut d = day21();
+        d.watch(5);
+        assert_eq!(
+            d.cont(),
+            Stop::Watchpoint {
+                reg: 5,
+                old: 0,
+                new: 123
+            }
+        );
+        assert_eq!(d.prog.ip, 1);
+        assert_eq!(d.cycles(), 1);
+    }
+
+    #[test]
+    fn trace() {
+        let mut d = day21();
+        d.set_trace(true);
+        assert_eq!(d.step(3), Stop::Stepped);
+        let t = d.trace();
+        assert_eq!(t.len(), 3);
+        assert_eq!(t[0].ip, 0);
+        assert_eq!(t[0].after[5], 123);
+        assert_eq!(t[2].inst.to_string(), "eqri 5 72 5");
+        assert_eq!(t[2].after[5], 1);
+    }
+
+    #[test]
+    fn halts() {
+        let mut d = Debugger::new("#ip 0\nseti 9 0 0\n".parse().unwrap());
+        assert_eq!(d.cont(), Stop::Halted);
+        assert_eq!(d.step(1), Stop::Halted);
+    }
+
+    #[test]
+    fn faults() {
+        let mut d = Debugger::new("#ip 0\nseti 9 0 1\naddr 1 7 2\n".parse().unwrap());
+        let stop = d.cont();
+        match &stop {
+            Stop::Fault(fault) => {
+                assert_eq!((fault.ip, fault.kind), (1, FaultKind::BadRegister(7)))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(d.cycles(), 1);
+        assert_eq!(d.step(1), stop);
+        assert!(d
+            .status()
+            .contains("fault: no register 7 in `addr 1 7 2` at ip 1"));
+    }
+
+    #[test]
+    fn commands() {
+        let mut d = day21();
+        assert_eq!(
+            d.command("break 28 if r5 != 0").unwrap(),
+            "breakpoint at 28 if r5 != 0"
+        );
+        assert!(d
+            .command("continue")
+            .unwrap()
+            .starts_with("breakpoint at 28\nip=28 "));
+        assert_eq!(d.prog.reg[5], 202209);
+        assert!(d.command("set r0 202209").is_ok());
+        assert!(d.command("delete 28").is_ok());
+        assert_eq!(d.command("c").unwrap().lines().next(), Some("halted"));
+        assert!(d.command("watch r9").is_err());
+        assert!(d.command("frobnicate").is_err());
+    }
+
+    #[test]
+    fn rewind() {
+        let mut d = day21();
+        d.step(10);
+        assert_eq!(d.command("checkpoint").unwrap(), "checkpoint 0 at cycle 10");
+        let saved = d.status();
+        d.step(1000);
+        assert_ne!(d.status(), saved);
+        assert_eq!(d.command("rewind 0").unwrap(), saved);
+        assert_eq!(d.cycles(), 10);
+        assert_eq!(d.command("rewind 1").unwrap_err(), "no checkpoint 1");
+    }
+}
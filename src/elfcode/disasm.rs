@@ -0,0 +1,267 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Disassemble ElfCode into readable pseudo-code.
+//!
+//! Each instruction is shown as an assignment like `r4 = r4 + r5`. Writes
+//! to the IP register are shown as jumps, with their targets labelled.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use super::{Inst, Opcode, Operand, Program, Word};
+
+/// How an instruction affects control flow.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Flow {
+    /// Continue at the next instruction.
+    Next,
+    /// Always continue at this address, which may be outside the program,
+    /// causing it to halt. A target too large to represent is
+    /// `usize::MAX`, which also halts.
+    Goto(usize),
+    /// Continue at `target` if register `reg`, which holds the result of
+    /// a comparison, is 1; otherwise at the next instruction.
+    Branch { reg: usize, target: usize },
+    /// Jump to an address computed at runtime from other registers, or
+    /// one that can't be worked out statically because computing it
+    /// would fault.
+    Dynamic,
+}
+
+/// Work out how the instruction at `pc` affects control flow.
+pub fn flow(ip_reg: Option<usize>, code: &[Inst], pc: usize) -> Flow {
+    flow_in(Word::default(), ip_reg, code, pc)
+}
+
+/// Work out how the instruction at `pc` affects control flow, on a machine
+/// with the given word.
+pub fn flow_in(word: Word, ip_reg: Option<usize>, code: &[Inst], pc: usize) -> Flow {
+    let inst = &code[pc];
+    let ip_reg = match ip_reg {
+        Some(r) if r == inst.c => r,
+        _ => return Flow::Next,
+    };
+    let others: Vec<usize> = inst.reads().into_iter().filter(|r| *r != ip_reg).collect();
+    if others.is_empty() {
+        // Depends only on the IP and literals, so can be worked out now,
+        // unless it overflows, in which case leave it to the machine.
+        let mut reg = vec![0; ip_reg + 1];
+        reg[ip_reg] = pc;
+        return match inst.opcode.eval_in(word, &reg, inst.a, inst.b) {
+            // Like `Program::step`, an IP that can't be incremented halts.
+            Ok(t) => Flow::Goto(t.saturating_add(1)),
+            Err(_) => Flow::Dynamic,
+        };
+    }
+    if inst.opcode == Opcode::Addr && others.len() == 1 && inst.reads().contains(&ip_reg) {
+        // Adding a boolean from the immediately preceding comparison
+        // conditionally skips the next instruction. The register only
+        // certainly holds a boolean if the comparison is the only way in.
+        let reg = others[0];
+        if pc > 0
+            && code[pc - 1].c == reg
+            && code[pc - 1].opcode.is_comparison()
+            && !jumped_to(word, ip_reg, code, pc)
+        {
+            return Flow::Branch {
+                reg,
+                target: pc + 2,
+            };
+        }
+    }
+    Flow::Dynamic
+}
+
+/// True if some instruction other than the one before `pc` might
+/// statically jump to `pc`.
+///
+/// Computed jumps could land anywhere and aren't counted.
+fn jumped_to(word: Word, ip_reg: usize, code: &[Inst], pc: usize) -> bool {
+    code.iter().enumerate().any(|(from, inst)| {
+        if inst.c != ip_reg || from + 1 == pc {
+            return false;
+        }
+        let reads = inst.reads();
+        if reads.iter().all(|r| *r == ip_reg) {
+            let mut reg = vec![0; ip_reg + 1];
+            reg[ip_reg] = from;
+            inst.opcode.eval_in(word, &reg, inst.a, inst.b) == Ok(pc - 1)
+        } else {
+            // Possibly a conditional skip over the next instruction.
+            inst.opcode == Opcode::Addr && reads.contains(&ip_reg) && from + 2 == pc
+        }
+    })
+}
+
+fn reg_name(ip_reg: Option<usize>, r: usize) -> String {
+    if Some(r) == ip_reg {
+        "ip".to_owned()
+    } else {
+        format!("r{}", r)
+    }
+}
+
+/// Render the right hand side of an instruction as an expression.
+pub fn expr(ip_reg: Option<usize>, inst: &Inst) -> String {
+    let (oa, ob) = inst.opcode.operands();
+    let name = |o, v| match o {
+        Operand::Reg => reg_name(ip_reg, v),
+        _ => v.to_string(),
+    };
+    let a = name(oa, inst.a);
+    let b = name(ob, inst.b);
+    let op = match inst.opcode {
+        Opcode::Setr | Opcode::Seti => return a,
+        Opcode::Addr | Opcode::Addi => "+",
+        Opcode::Mulr | Opcode::Muli => "*",
+        Opcode::Banr | Opcode::Bani => "&",
+        Opcode::Borr | Opcode::Bori => "|",
+        Opcode::Gtir | Opcode::Gtri | Opcode::Gtrr => ">",
+        Opcode::Eqir | Opcode::Eqri | Opcode::Eqrr => "==",
+    };
+    format!("{} {} {}", a, op, b)
+}
+
+fn label(code_len: usize, target: usize) -> String {
+    if target >= code_len {
+        "halt".to_owned()
+    } else {
+        format!("goto L{}", target)
+    }
+}
+
+/// Render one instruction as pseudo-code.
+pub fn pseudo(ip_reg: Option<usize>, code: &[Inst], pc: usize) -> String {
+    let inst = &code[pc];
+    match flow(ip_reg, code, pc) {
+        Flow::Next => format!("{} = {}", reg_name(ip_reg, inst.c), expr(ip_reg, inst)),
+        Flow::Goto(target) => label(code.len(), target),
+        Flow::Branch { reg, target } => format!("if r{} {}", reg, label(code.len(), target)),
+        Flow::Dynamic => format!("ip = {}  # computed jump", expr(ip_reg, inst)),
+    }
+}
+
+/// All the addresses inside the program that are static jump targets.
+pub fn jump_targets(ip_reg: Option<usize>, code: &[Inst]) -> BTreeSet<usize> {
+    (0..code.len())
+        .filter_map(|pc| match flow(ip_reg, code, pc) {
+            Flow::Goto(t) | Flow::Branch { target: t, .. } => Some(t),
+            _ => None,
+        })
+        .filter(|t| *t < code.len())
+        .collect()
+}
+
+/// Produce an annotated listing of the whole program.
+pub fn disassemble(prog: &Program) -> String {
+    let code = &prog.code;
+    let ip_reg = prog.ip_reg;
+    let targets = jump_targets(ip_reg, code);
+    let mut s = String::new();
+    if let Some(r) = ip_reg {
+        writeln!(s, "#ip {}  # ip is r{}", r, r).unwrap();
+    }
+    for (pc, inst) in code.iter().enumerate() {
+        if targets.contains(&pc) {
+            writeln!(s, "L{}:", pc).unwrap();
+        }
+        writeln!(
+            s,
+            "{:>6}  {:<19} {}",
+            pc,
+            inst.to_string(),
+            pseudo(ip_reg, code, pc)
+        )
+        .unwrap();
+    }
+    s
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn day19() -> Program {
+        include_str!("../../input/input19.txt").parse().unwrap()
+    }
+
+    fn day21() -> Program {
+        include_str!("../../input/input21.txt").parse().unwrap()
+    }
+
+    #[test]
+    fn flows() {
+        let p = day19();
+        let f = |pc| flow(p.ip_reg, &p.code, pc);
+        assert_eq!(f(0), Flow::Goto(17));
+        assert_eq!(f(1), Flow::Next);
+        assert_eq!(f(5), Flow::Branch { reg: 4, target: 7 });
+        assert_eq!(f(6), Flow::Goto(8));
+        assert_eq!(f(11), Flow::Goto(3));
+        assert_eq!(f(16), Flow::Goto(257));
+        assert_eq!(f(25), Flow::Dynamic);
+    }
+
+    #[test]
+    fn huge_targets() {
+        let text =
+            "#ip 0\nseti 18446744073709551615 0 0\nseti 0 0 1\nmuli 0 18446744073709551615 0\n";
+        let p: Program = text.parse().unwrap();
+        assert_eq!(flow(p.ip_reg, &p.code, 0), Flow::Goto(usize::MAX));
+        // 2 * MAX overflows.
+        assert_eq!(flow(p.ip_reg, &p.code, 2), Flow::Dynamic);
+        assert_eq!(pseudo(p.ip_reg, &p.code, 0), "halt");
+    }
+
+    #[test]
+    fn jump_between_compare_and_branch() {
+        // The `addr` at 3 is reached from 1 without running the `eqrr`, so
+        // r1 might be anything.
+        let p: Program = "#ip 4\nseti 3 0 1\nseti 2 0 4\neqrr 0 0 1\naddr 1 4 4\nseti 11 0 0\n"
+            .parse()
+            .unwrap();
+        assert_eq!(flow(p.ip_reg, &p.code, 3), Flow::Dynamic);
+    }
+
+    #[test]
+    fn pseudo_code() {
+        let p = day21();
+        let l = |pc| pseudo(p.ip_reg, &p.code, pc);
+        assert_eq!(l(0), "r5 = 123");
+        assert_eq!(l(3), "if r5 goto L5");
+        assert_eq!(l(4), "goto L1");
+        assert_eq!(l(9), "r5 = r5 + r2");
+        assert_eq!(l(13), "r2 = 256 > r3");
+        assert_eq!(l(16), "goto L28");
+        assert_eq!(l(29), "if r2 halt");
+    }
+
+    #[test]
+    fn listing() {
+        let s = disassemble(&day19());
+        let lines: Vec<&str> = s.lines().collect();
+        assert_eq!(lines[0], "#ip 5  # ip is r5");
+        assert_eq!(lines[1], "     0  addi 5 16 5         goto L17");
+        assert!(lines.contains(&"L3:"));
+        assert!(lines.contains(&"    16  mulr 5 5 5          halt"));
+        assert!(lines.contains(&"    25  addr 5 0 5          ip = ip + r0  # computed jump"));
+
+        let p: Program = "#ip 1\nseti 1234567890 1234567890 3\n".parse().unwrap();
+        assert_eq!(
+            disassemble(&p).lines().nth(1),
+            Some("     0  seti 1234567890 1234567890 3 r3 = 1234567890")
+        );
+    }
+}
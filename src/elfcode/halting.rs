@@ -0,0 +1,234 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Find which inputs make a day-21-style program halt.
+//!
+//! These programs never write register 0, and only read it in an `eqrr`
+//! that decides whether to halt. So, running the program and looking at
+//! what register 0 is compared to gives the sequence of inputs that would
+//! make it halt, in the order they'd do so.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use super::{Fault, Opcode, Program, Reg};
+
+/// An `eqrr` at `ip` that compares register `reg` to register 0.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HaltCheck {
+    pub ip: usize,
+    pub reg: usize,
+}
+
+/// Find instructions that compare register 0 to another register.
+///
+/// Returns an empty list if anything writes register 0, or reads it other
+/// than in such a comparison, or it's the IP register, since then it's not
+/// just an input.
+pub fn find_halt_checks(prog: &Program) -> Vec<HaltCheck> {
+    if prog.ip_reg == Some(0) {
+        return Vec::new();
+    }
+    let mut checks = Vec::new();
+    for (ip, inst) in prog.code.iter().enumerate() {
+        if inst.opcode == Opcode::Eqrr && (inst.a == 0) != (inst.b == 0) {
+            checks.push(HaltCheck {
+                ip,
+                reg: if inst.a == 0 { inst.b } else { inst.a },
+            });
+        } else if inst.reads().contains(&0) {
+            return Vec::new();
+        }
+        if inst.c == 0 {
+            return Vec::new();
+        }
+    }
+    checks
+}
+
+/// Why the halting inputs couldn't be found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HaltingError {
+    /// Register 0 isn't only used as an input to halt checks.
+    NoChecks,
+    /// The program faulted before all the values were found.
+    Fault(Fault),
+}
+
+impl fmt::Display for HaltingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HaltingError::NoChecks => {
+                write!(f, "register 0 is not only compared to decide halting")
+            }
+            HaltingError::Fault(fault) => write!(f, "{}", fault),
+        }
+    }
+}
+
+impl std::error::Error for HaltingError {}
+
+/// The inputs for register 0 that make a program halt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HaltingValues {
+    /// Values that would halt the program, in the order they're checked,
+    /// without repeats.
+    pub values: Vec<Reg>,
+    /// True if the program was found to be in a loop that repeats the same
+    /// checks forever, so there are no more values to find.
+    pub cycled: bool,
+}
+
+impl HaltingValues {
+    /// The input that halts after the fewest instructions.
+    pub fn fastest(&self) -> Option<Reg> {
+        self.values.first().cloned()
+    }
+
+    /// The input that halts after the most instructions.
+    pub fn slowest(&self) -> Option<Reg> {
+        self.values.last().cloned()
+    }
+}
+
+/// Run the program to enumerate the inputs that would make it halt.
+///
+/// This runs until the machine state at a check repeats, which means
+/// it's in a cycle and no new values can appear, or until the program
+/// halts by some other route. It fails if there are no checks against
+/// register 0, or if the program faults.
+pub fn halting_values(prog: &Program) -> Result<HaltingValues, HaltingError> {
+    let checks = find_halt_checks(prog);
+    if checks.is_empty() {
+        return Err(HaltingError::NoChecks);
+    }
+    let mut prog = prog.clone();
+    prog.optimize();
+    let mut seen_values = HashSet::new();
+    let mut seen_states = HashSet::new();
+    let mut values = Vec::new();
+    while prog.run_until(|p| checks.iter().any(|c| c.ip == p.ip)) {
+        let check = checks.iter().find(|c| c.ip == prog.ip).unwrap();
+        // Register 0 is only read by the checks, so it's not part of the
+        // state.
+        prog.reg[0] = 0;
+        if !seen_states.insert((prog.ip, prog.reg.clone())) {
+            return Ok(HaltingValues {
+                values,
+                cycled: true,
+            });
+        }
+        let v = prog.reg[check.reg];
+        if seen_values.insert(v) {
+            values.push(v);
+        }
+        // Make sure this check fails, so we go on to see the rest.
+        prog.reg[0] = !v;
+        prog.step();
+    }
+    if let Some(fault) = prog.fault {
+        return Err(HaltingError::Fault(fault));
+    }
+    Ok(HaltingValues {
+        values,
+        cycled: false,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn day21() {
+        let p: Program = include_str!("../../input/input21.txt").parse().unwrap();
+        assert_eq!(find_halt_checks(&p), vec![HaltCheck { ip: 28, reg: 5 }]);
+        let hv = halting_values(&p).unwrap();
+        assert!(hv.cycled);
+        assert_eq!(hv.fastest(), Some(202209));
+        assert_eq!(hv.slowest(), Some(11777564));
+    }
+
+    #[test]
+    fn counter() {
+        // Count r1 up mod 4, halting if it equals r0.
+        let p: Program = "#ip 5
+seti 0 0 1
+addi 1 1 1
+bani 1 3 1
+eqrr 1 0 2
+addr 2 5 5
+seti 0 0 5
+"
+        .parse()
+        .unwrap();
+        let hv = halting_values(&p).unwrap();
+        assert_eq!(hv.values, vec![1, 2, 3, 0]);
+        assert!(hv.cycled);
+        assert_eq!(hv.fastest(), Some(1));
+        assert_eq!(hv.slowest(), Some(0));
+    }
+
+    #[test]
+    fn writes_r0() {
+        let p: Program = include_str!("../../input/input19.txt").parse().unwrap();
+        assert!(find_halt_checks(&p).is_empty());
+        assert_eq!(halting_values(&p), Err(HaltingError::NoChecks));
+    }
+
+    #[test]
+    fn reads_r0() {
+        let p: Program = "#ip 5\neqrr 1 0 2\naddi 0 1 3\n".parse().unwrap();
+        assert!(find_halt_checks(&p).is_empty());
+        assert_eq!(halting_values(&p), Err(HaltingError::NoChecks));
+    }
+
+    #[test]
+    fn compared_to_max() {
+        // Compares r1 to r0 when it's MAX, then 7 forever.
+        let p: Program = "#ip 5
+seti 18446744073709551615 0 1
+eqrr 1 0 2
+addr 2 5 5
+seti 4 0 5
+seti 99 0 5
+seti 7 0 1
+eqrr 1 0 2
+addr 2 5 5
+seti 0 0 5
+"
+        .parse()
+        .unwrap();
+        let hv = halting_values(&p).unwrap();
+        assert_eq!(hv.values, vec![Reg::MAX, 7]);
+        assert!(hv.cycled);
+    }
+
+    #[test]
+    fn r0_is_ip() {
+        // Comparing to the IP isn't a halt check, however it's used.
+        let p: Program = "#ip 0\nseti 1 0 1\neqrr 1 0 2\n".parse().unwrap();
+        assert!(find_halt_checks(&p).is_empty());
+        assert_eq!(halting_values(&p), Err(HaltingError::NoChecks));
+    }
+
+    #[test]
+    fn faults() {
+        let p: Program = "#ip 5\neqrr 1 0 2\naddr 1 9 1\n".parse().unwrap();
+        match halting_values(&p) {
+            Err(HaltingError::Fault(fault)) => assert_eq!(fault.ip, 1),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
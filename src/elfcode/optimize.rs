@@ -0,0 +1,498 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recognize slow loop idioms in ElfCode and run them natively.
+//!
+//! Idioms are found by matching instruction templates, allowing for any
+//! choice of registers and for swapped operands of commutative opcodes.
+//! When the program reaches the first instruction of an idiom, the whole
+//! loop is replaced by one native computation that leaves the registers and
+//! IP exactly as the loop would have.
+
+use super::Opcode::{self, *};
+use super::{Program, Reg};
+
+/// A loop that can be computed directly.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Idiom {
+    /// From day 19: for a fixed `i`, step `j` up past `n`, adding `i` to
+    /// `acc` if `i * j == n`. `t` is a scratch register.
+    DivisorSum {
+        i: usize,
+        j: usize,
+        n: usize,
+        acc: usize,
+        t: usize,
+    },
+    /// From day 19: the loop around `DivisorSum`, running `i` from 1 past
+    /// `n`, which adds the sum of the factors of `n` to `acc`.
+    FactorSum {
+        i: usize,
+        j: usize,
+        n: usize,
+        acc: usize,
+        t: usize,
+    },
+    /// From day 21: find the smallest `q` such that `(q + 1) * k > n`,
+    /// which is `n / k`. `t` is a scratch register.
+    Divide {
+        q: usize,
+        n: usize,
+        k: Reg,
+        t: usize,
+    },
+}
+
+/// Part of an instruction template.
+#[derive(Debug, Copy, Clone)]
+enum Pat {
+    /// Some register other than the IP, which must be used consistently
+    /// throughout the template, and differ from the other variables.
+    Var(usize),
+    /// The register bound to the IP.
+    Ip,
+    /// This literal value.
+    Lit(Reg),
+    /// A literal address, relative to the start of the template.
+    Rel(isize),
+    /// Anything.
+    Any,
+}
+use self::Pat::*;
+
+type Template = [(Opcode, Pat, Pat, Pat)];
+
+const DIVISOR_SUM: &Template = &[
+    (Mulr, Var(0), Var(1), Var(2)),
+    (Eqrr, Var(2), Var(3), Var(2)),
+    (Addr, Var(2), Ip, Ip),
+    (Addi, Ip, Lit(1), Ip),
+    (Addr, Var(0), Var(4), Var(4)),
+    (Addi, Var(1), Lit(1), Var(1)),
+    (Gtrr, Var(1), Var(3), Var(2)),
+    (Addr, Ip, Var(2), Ip),
+    (Seti, Rel(-1), Any, Ip),
+];
+
+const FACTOR_SUM: &Template = &[
+    (Seti, Lit(1), Any, Var(0)),
+    (Seti, Lit(1), Any, Var(1)),
+    (Mulr, Var(0), Var(1), Var(2)),
+    (Eqrr, Var(2), Var(3), Var(2)),
+    (Addr, Var(2), Ip, Ip),
+    (Addi, Ip, Lit(1), Ip),
+    (Addr, Var(0), Var(4), Var(4)),
+    (Addi, Var(1), Lit(1), Var(1)),
+    (Gtrr, Var(1), Var(3), Var(2)),
+    (Addr, Ip, Var(2), Ip),
+    (Seti, Rel(1), Any, Ip),
+    (Addi, Var(0), Lit(1), Var(0)),
+    (Gtrr, Var(0), Var(3), Var(2)),
+    (Addr, Var(2), Ip, Ip),
+    (Seti, Rel(0), Any, Ip),
+];
+
+const DIVIDE: &Template = &[
+    (Seti, Lit(0), Any, Var(0)),
+    (Addi, Var(0), Lit(1), Var(1)),
+    (Muli, Var(1), Any, Var(1)),
+    (Gtrr, Var(1), Var(2), Var(1)),
+    (Addr, Var(1), Ip, Ip),
+    (Addi, Ip, Lit(1), Ip),
+    (Seti, Rel(8), Any, Ip),
+    (Addi, Var(0), Lit(1), Var(0)),
+    (Seti, Rel(0), Any, Ip),
+];
+
+fn is_commutative(op: Opcode) -> bool {
+    matches!(op, Addr | Mulr | Banr | Borr | Eqrr)
+}
+
+struct Matcher<'a> {
+    prog: &'a Program,
+    ip_reg: usize,
+    start: usize,
+}
+
+impl<'a> Matcher<'a> {
+    fn arg(&self, pat: Pat, v: Reg, vars: &mut Vec<Option<usize>>) -> bool {
+        match pat {
+            Any => true,
+            Lit(l) => v == l,
+            Rel(r) => v as isize == self.start as isize + r,
+            Ip => v == self.ip_reg,
+            Var(i) => {
+                if i >= vars.len() {
+                    vars.resize(i + 1, None);
+                }
+                match vars[i] {
+                    Some(r) => r == v,
+                    None if v == self.ip_reg || vars.contains(&Some(v)) => false,
+                    None => {
+                        vars[i] = Some(v);
+                        true
+                    }
+                }
+            }
+        }
+    }
+
+    /// Try to match the template from instruction `k`, with some variables
+    /// already bound, returning the complete bindings.
+    fn matches(&self, t: &Template, k: usize, vars: &[Option<usize>]) -> Option<Vec<usize>> {
+        if k == t.len() {
+            return vars.iter().cloned().collect();
+        }
+        let inst = self.prog.code.get(self.start + k)?;
+        let (op, pa, pb, pc) = t[k];
+        if inst.opcode != op {
+            return None;
+        }
+        let mut orders = vec![(pa, pb)];
+        if is_commutative(op) {
+            orders.push((pb, pa));
+        }
+        for (pa, pb) in orders {
+            let mut vars = vars.to_vec();
+            if self.arg(pa, inst.a, &mut vars)
+                && self.arg(pb, inst.b, &mut vars)
+                && self.arg(pc, inst.c, &mut vars)
+            {
+                if let Some(r) = self.matches(t, k + 1, &vars) {
+                    return Some(r);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Look for an idiom starting at `start`.
+pub fn idiom_at(prog: &Program, start: usize) -> Option<Idiom> {
+    let m = Matcher {
+        prog,
+        ip_reg: prog.ip_reg?,
+        start,
+    };
+    if let Some(v) = m.matches(FACTOR_SUM, 0, &[]) {
+        return Some(Idiom::FactorSum {
+            i: v[0],
+            j: v[1],
+            t: v[2],
+            n: v[3],
+            acc: v[4],
+        });
+    }
+    if let Some(v) = m.matches(DIVISOR_SUM, 0, &[]) {
+        return Some(Idiom::DivisorSum {
+            i: v[0],
+            j: v[1],
+            t: v[2],
+            n: v[3],
+            acc: v[4],
+        });
+    }
+    if let Some(v) = m.matches(DIVIDE, 0, &[]) {
+        let k = prog.code[start + 2].b;
+        if k > 0 {
+            return Some(Idiom::Divide {
+                q: v[0],
+                t: v[1],
+                n: v[2],
+                k,
+            });
+        }
+    }
+    None
+}
+
+/// Find all the idioms in a program, indexed by their start address.
+pub fn find_idioms(prog: &Program) -> Vec<Option<Idiom>> {
+    (0..prog.code.len()).map(|s| idiom_at(prog, s)).collect()
+}
+
+/// Sum of all the factors of `n`, including 1 and `n`.
+fn factor_sum(n: Reg) -> Reg {
+    let mut sum = 0;
+    let mut f = 1;
+    while f * f <= n {
+        if n.is_multiple_of(f) {
+            sum += f;
+            if f * f != n {
+                sum += n / f;
+            }
+        }
+        f += 1;
+    }
+    sum
+}
+
+impl Idiom {
+    /// Number of instructions in the idiom's code.
+    pub fn code_len(&self) -> usize {
+        match self {
+            Idiom::DivisorSum { .. } => DIVISOR_SUM.len(),
+            Idiom::FactorSum { .. } => FACTOR_SUM.len(),
+            Idiom::Divide { .. } => DIVIDE.len(),
+        }
+    }
+
+    /// Run the whole loop, leaving the program just after it.
+    ///
+    /// Returns false, leaving the program untouched, if the loop would
+    /// overflow or use a register that doesn't exist. The interpreter should
+    /// then run the loop itself, so that it faults, wraps or saturates just
+    /// as it would have without the optimization.
+    pub fn apply(&self, prog: &mut Program) -> bool {
+        let ip_reg = prog.ip_reg.unwrap();
+        let start = prog.ip;
+        let r = &mut prog.reg;
+        let regs: &[usize] = match self {
+            Idiom::DivisorSum { i, j, n, acc, t } | Idiom::FactorSum { i, j, n, acc, t } => {
+                &[*i, *j, *n, *acc, *t]
+            }
+            Idiom::Divide { q, n, t, .. } => &[*q, *n, *t],
+        };
+        if regs.iter().chain(Some(&ip_reg)).any(|&x| x >= r.len()) {
+            return false;
+        }
+        match *self {
+            Idiom::DivisorSum { i, j, n, acc, t } => {
+                // The body always runs at least once.
+                let (ri, j0, rn) = (r[i], r[j], r[n]);
+                let j_end = match (j0.checked_add(1), rn.checked_add(1)) {
+                    (Some(a), Some(b)) => a.max(b),
+                    _ => return false,
+                };
+                // The loop computes every `i * j` up to the last.
+                if ri.checked_mul(j_end - 1).is_none() {
+                    return false;
+                }
+                if ri > 0 && rn.is_multiple_of(ri) && j0 <= rn / ri && rn / ri < j_end {
+                    match r[acc].checked_add(ri) {
+                        Some(v) => r[acc] = v,
+                        None => return false,
+                    }
+                }
+                r[j] = j_end;
+                r[t] = 1;
+            }
+            Idiom::FactorSum { i, j, n, acc, t } => {
+                let rn = r[n];
+                // Both loops always run at least once, and multiply every
+                // pair of numbers up to `n`.
+                let top = rn.max(1);
+                if top.checked_mul(top).is_none() {
+                    return false;
+                }
+                match r[acc].checked_add(factor_sum(rn)) {
+                    Some(v) => r[acc] = v,
+                    None => return false,
+                }
+                r[i] = (rn + 1).max(2);
+                r[j] = (rn + 1).max(2);
+                r[t] = 1;
+            }
+            Idiom::Divide { q, n, k, t } => {
+                // The last product tried is `(q + 1) * k`.
+                let rq = r[n] / k;
+                if (rq + 1).checked_mul(k).is_none() {
+                    return false;
+                }
+                r[q] = rq;
+                r[t] = 1;
+            }
+        }
+        // The last instruction executed was a jump to the exit.
+        r[ip_reg] = start + self.code_len() - 1;
+        prog.ip = start + self.code_len();
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::elfcode::{FaultKind, Overflow, Word};
+
+    fn day19() -> Program {
+        include_str!("../../input/input19.txt").parse().unwrap()
+    }
+
+    fn day21() -> Program {
+        include_str!("../../input/input21.txt").parse().unwrap()
+    }
+
+    #[test]
+    fn find_day19() {
+        let p = day19();
+        let found: Vec<(usize, Idiom)> = find_idioms(&p)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(s, i)| i.map(|i| (s, i)))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    1,
+                    Idiom::FactorSum {
+                        i: 1,
+                        j: 2,
+                        n: 3,
+                        acc: 0,
+                        t: 4
+                    }
+                ),
+                (
+                    3,
+                    Idiom::DivisorSum {
+                        i: 1,
+                        j: 2,
+                        n: 3,
+                        acc: 0,
+                        t: 4
+                    }
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn find_day21() {
+        assert_eq!(
+            idiom_at(&day21(), 17),
+            Some(Idiom::Divide {
+                q: 2,
+                n: 3,
+                k: 256,
+                t: 1
+            })
+        );
+    }
+
+    #[test]
+    fn commuted_operands() {
+        let mut p = day19();
+        p.code[3] = "mulr 2 1 4".parse().unwrap();
+        p.code[4] = "eqrr 3 4 4".parse().unwrap();
+        assert!(idiom_at(&p, 3).is_some());
+        // But registers must still be used consistently.
+        p.code[8] = "addi 4 1 4".parse().unwrap();
+        assert!(idiom_at(&p, 3).is_none());
+    }
+
+    #[test]
+    fn same_result_as_interpreter() {
+        let mut slow = day19();
+        let mut fast = day19();
+        fast.optimize();
+        assert_eq!(slow.eval(), 1302);
+        assert_eq!(fast.eval(), 1302);
+        assert_eq!(slow.reg, fast.reg);
+        assert_eq!(slow.ip, fast.ip);
+    }
+
+    #[test]
+    fn divide_same_as_interpreter() {
+        let mut slow = day21();
+        let mut fast = day21();
+        fast.optimize();
+        for _ in 0..3 {
+            slow.step();
+            assert!(slow.run_until(|p| p.ip == 28));
+            fast.step();
+            assert!(fast.run_until(|p| p.ip == 28));
+            assert_eq!(slow.reg, fast.reg);
+        }
+    }
+
+    #[test]
+    fn factor_sums() {
+        assert_eq!(factor_sum(0), 0);
+        assert_eq!(factor_sum(1), 1);
+        assert_eq!(factor_sum(12), 1 + 2 + 3 + 4 + 6 + 12);
+        assert_eq!(factor_sum(49), 1 + 7 + 49);
+    }
+
+    #[test]
+    fn inner_loop_only() {
+        // Entering the inner loop directly, as after a jump to 3, gives the
+        // same result as interpreting it.
+        let mut slow = day19();
+        let mut fast = day19();
+        fast.optimize();
+        for p in [&mut slow, &mut fast].iter_mut() {
+            p.ip = 3;
+            p.reg = vec![0, 3, 1, 12, 0, 0];
+            assert!(p.run_until(|p| p.ip == 12));
+        }
+        assert_eq!(slow.reg, fast.reg);
+        assert_eq!(fast.reg[0], 3);
+    }
+
+    #[test]
+    fn factor_sum_small_input() {
+        // The whole nested loop, on a small enough number to interpret.
+        let mut slow = day19();
+        let mut fast = day19();
+        fast.optimize();
+        for p in [&mut slow, &mut fast].iter_mut() {
+            p.ip = 1;
+            p.reg = vec![0, 0, 0, 60, 0, 0];
+            assert!(p.run_until(|p| p.ip == 16));
+        }
+        assert_eq!(slow.reg, fast.reg);
+        assert_eq!(fast.reg[0], 168);
+    }
+
+    #[test]
+    fn overflow_faults_like_interpreter() {
+        let mut slow = day19();
+        let mut fast = day19();
+        fast.optimize();
+        for p in [&mut slow, &mut fast].iter_mut() {
+            p.ip = 3;
+            p.reg = vec![0, 3, Reg::MAX / 3 - 2, Reg::MAX - 1, 0, 0];
+        }
+        let fault = slow.run().unwrap_err();
+        assert_eq!(fault.kind, FaultKind::Overflow);
+        assert_eq!(fast.run().unwrap_err(), fault);
+    }
+
+    #[test]
+    fn narrowed_after_optimize() {
+        // Products wrap at 8 bits, so the native loop would get this wrong.
+        let word = Word::new(8, Overflow::Wrap);
+        let mut slow = day19();
+        let mut fast = day19();
+        fast.optimize();
+        for p in [&mut slow, &mut fast].iter_mut() {
+            p.word = word;
+            p.ip = 1;
+            p.reg = vec![0, 0, 0, 60, 0, 0];
+            assert!(p.run_until(|p| p.ip == 16));
+        }
+        assert_eq!(slow.reg, fast.reg);
+        assert_ne!(fast.reg[0], 168);
+    }
+
+    #[test]
+    fn day19_part_two() {
+        let mut p = day19();
+        p.reg[0] = 1;
+        p.optimize();
+        assert_eq!(p.eval(), 13083798);
+    }
+}
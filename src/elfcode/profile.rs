@@ -0,0 +1,180 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Count where a program spends its time.
+//!
+//! Turn on profiling with `Program::start_profile`, run the program, and
+//! then look at `Program::profile`, or print `Profile::report` for a listing
+//! with the hottest instructions first.
+//!
+//! Loops run natively after `Program::optimize` aren't broken down by
+//! instruction, so they're counted separately, and their instructions look
+//! colder than they really are.
+
+use std::fmt::Write;
+
+use super::disasm::pseudo;
+use super::Program;
+
+/// Execution counts collected while a program runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    /// Number of times each instruction was interpreted, indexed by
+    /// address.
+    pub ip_counts: Vec<u64>,
+    /// Number of times an optimized loop starting at each address ran
+    /// natively, in a single step.
+    pub idiom_counts: Vec<u64>,
+    /// Number of times each register was written by an interpreted
+    /// instruction.
+    pub reg_writes: Vec<u64>,
+    /// Total steps taken: instructions interpreted, plus optimized loops.
+    pub cycles: u64,
+}
+
+impl Profile {
+    pub fn new(code_len: usize, nreg: usize) -> Profile {
+        Profile {
+            ip_counts: vec![0; code_len],
+            idiom_counts: vec![0; code_len],
+            reg_writes: vec![0; nreg],
+            cycles: 0,
+        }
+    }
+
+    /// Count one execution of the instruction at `ip`, writing register
+    /// `c`.
+    pub fn record(&mut self, ip: usize, c: usize) {
+        self.ip_counts[ip] += 1;
+        self.reg_writes[c] += 1;
+        self.cycles += 1;
+    }
+
+    /// Count one run of the optimized loop starting at `ip`.
+    pub fn record_idiom(&mut self, ip: usize) {
+        self.idiom_counts[ip] += 1;
+        self.cycles += 1;
+    }
+
+    /// Addresses that ran at least once, hottest first, and in address
+    /// order among equals.
+    pub fn hottest(&self) -> Vec<usize> {
+        let mut ips: Vec<usize> = (0..self.ip_counts.len())
+            .filter(|ip| self.ip_counts[*ip] > 0)
+            .collect();
+        ips.sort_by_key(|ip| std::cmp::Reverse(self.ip_counts[*ip]));
+        ips
+    }
+
+    /// A listing of the instructions that ran, hottest first, with their
+    /// counts and share of all cycles, followed by any optimized loops and
+    /// then register write counts.
+    pub fn report(&self, prog: &Program) -> String {
+        let mut s = String::new();
+        writeln!(s, "{} cycles", self.cycles).unwrap();
+        for ip in self.hottest() {
+            let n = self.ip_counts[ip];
+            writeln!(
+                s,
+                "{:>12} {:>5.1}% {:>4}  {}",
+                n,
+                100.0 * n as f64 / self.cycles as f64,
+                ip,
+                pseudo(prog.ip_reg, &prog.code, ip)
+            )
+            .unwrap();
+        }
+        if self.idiom_counts.iter().any(|n| *n > 0) {
+            writeln!(
+                s,
+                "optimized loops, whose instructions aren't counted above:"
+            )
+            .unwrap();
+            for (ip, n) in self.idiom_counts.iter().enumerate() {
+                if *n > 0 {
+                    writeln!(
+                        s,
+                        "{:>12} {:>5.1}% {:>4}  loop",
+                        n,
+                        100.0 * *n as f64 / self.cycles as f64,
+                        ip
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        writeln!(s, "register writes:").unwrap();
+        for (r, n) in self.reg_writes.iter().enumerate() {
+            let ip_note = if Some(r) == prog.ip_reg { "  (ip)" } else { "" };
+            writeln!(s, "{:>12}   r{}{}", n, r, ip_note).unwrap();
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn day19_hot_loop() {
+        let mut p: Program = include_str!("../../input/input19.txt").parse().unwrap();
+        p.start_profile();
+        p.eval();
+        let prof = p.profile.as_ref().unwrap();
+        assert_eq!(prof.cycles, prof.ip_counts.iter().sum::<u64>());
+        assert_eq!(prof.cycles, prof.reg_writes.iter().sum::<u64>());
+        // The inner loop over r2, from 3 to 11, is where all the time goes.
+        let hot = prof.hottest();
+        let mut top: Vec<usize> = hot[..8].to_vec();
+        top.sort();
+        assert_eq!(top, vec![3, 4, 5, 6, 8, 9, 10, 11]);
+        let report = prof.report(&p);
+        assert!(report.starts_with(&format!("{} cycles\n", prof.cycles)));
+        assert!(report.contains("r5  (ip)\n"));
+        assert!(!report.contains("optimized"));
+    }
+
+    #[test]
+    fn optimized_loops() {
+        let mut p: Program = include_str!("../../input/input19.txt").parse().unwrap();
+        p.optimize();
+        p.start_profile();
+        p.eval();
+        let prof = p.profile.as_ref().unwrap();
+        assert_eq!(
+            prof.cycles,
+            prof.ip_counts.iter().sum::<u64>() + prof.idiom_counts.iter().sum::<u64>()
+        );
+        // The whole factor sum from 1 runs natively, once.
+        assert_eq!(prof.idiom_counts[1], 1);
+        assert_eq!(prof.ip_counts[1], 0);
+        assert_eq!(prof.ip_counts[3], 0);
+        let report = prof.report(&p);
+        assert!(report
+            .contains("optimized loops, whose instructions aren't counted above:\n           1"));
+        assert!(report.contains("   1  loop\n"));
+    }
+
+    #[test]
+    fn counts() {
+        let mut p: Program = "#ip 1\nseti 4 0 0\naddi 0 1 0\n".parse().unwrap();
+        p.start_profile();
+        p.eval();
+        let prof = p.profile.unwrap();
+        assert_eq!(prof.ip_counts, vec![1, 1]);
+        assert_eq!(prof.reg_writes, vec![2, 0, 0, 0, 0, 0]);
+        assert_eq!(prof.cycles, 2);
+    }
+}
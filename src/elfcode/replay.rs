@@ -0,0 +1,307 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Save and restore machine state, and record and replay executions.
+//!
+//! `Program::snapshot` and `Program::restore` checkpoint and rewind a
+//! single run. A `Trace` keeps the state after every step, and can be saved
+//! to a file and compared against another run to find where they first
+//! take different paths. For runs too long to keep in memory,
+//! `Trace::record_to` and `Trace::diverge_from` do the same while streaming
+//! the states to and from a file.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use super::{Fault, Program, Reg};
+
+/// The changeable state of a machine: everything but the code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub ip: usize,
+    pub reg: Vec<Reg>,
+    pub fault: Option<Fault>,
+}
+
+/// The state before a run and after each step of it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Trace {
+    pub states: Vec<Snapshot>,
+}
+
+impl Trace {
+    /// Run the program for up to `max_steps`, recording its state, and
+    /// leaving it where it stopped.
+    pub fn record(prog: &mut Program, max_steps: usize) -> Trace {
+        let mut states = vec![prog.snapshot()];
+        for _ in 0..max_steps {
+            if prog.is_halted() {
+                break;
+            }
+            prog.step();
+            states.push(prog.snapshot());
+        }
+        Trace { states }
+    }
+
+    /// Like `record`, but write each state to `w` in the `write_to` format
+    /// rather than keeping them. Returns the number of steps taken.
+    pub fn record_to<W: Write>(
+        prog: &mut Program,
+        max_steps: usize,
+        w: &mut W,
+    ) -> io::Result<usize> {
+        write_state(w, &prog.snapshot())?;
+        for steps in 0..max_steps {
+            if prog.is_halted() {
+                return Ok(steps);
+            }
+            prog.step();
+            write_state(w, &prog.snapshot())?;
+        }
+        Ok(max_steps)
+    }
+
+    /// Like recording the program for up to `max_steps` and calling
+    /// `first_divergence` against a trace read from `r`, but reading one
+    /// state at a time.
+    ///
+    /// Leaves the program where the runs diverged, or where it stopped.
+    pub fn diverge_from<R: BufRead>(
+        prog: &mut Program,
+        max_steps: usize,
+        r: R,
+    ) -> io::Result<Option<usize>> {
+        let mut lines = r.lines().enumerate();
+        // The program is always at state `i`, which is compared to line `i`.
+        let mut i = 0;
+        loop {
+            match lines.next() {
+                None => return Ok(Some(i)),
+                Some((n, l)) => {
+                    if parse_state(n, &l?)?.ip != prog.ip {
+                        return Ok(Some(i));
+                    }
+                }
+            }
+            if i == max_steps || prog.is_halted() {
+                return Ok(lines.next().map(|_| i + 1));
+            }
+            prog.step();
+            i += 1;
+        }
+    }
+
+    /// Rewind the program to the start of the trace and run it again,
+    /// checking that each step gets the same IP and registers.
+    ///
+    /// Returns the index of the first state that doesn't match.
+    pub fn replay(&self, prog: &mut Program) -> Result<(), usize> {
+        let mut states = self.states.iter().enumerate();
+        match states.next() {
+            Some((_, first)) => prog.restore(first),
+            None => return Ok(()),
+        }
+        for (i, s) in states {
+            prog.step();
+            if prog.ip != s.ip || prog.reg != s.reg {
+                return Err(i);
+            }
+        }
+        Ok(())
+    }
+
+    /// The index of the first state where the two traces are at different
+    /// IPs, or where one ends before the other, scanning from the start.
+    ///
+    /// Registers aren't compared, since runs from different inputs usually
+    /// differ in register 0 from the start.
+    pub fn first_divergence(&self, other: &Trace) -> Option<usize> {
+        let n = self.states.len().min(other.states.len());
+        (0..n)
+            .find(|i| self.states[*i].ip != other.states[*i].ip)
+            .or(if self.states.len() == other.states.len() {
+                None
+            } else {
+                Some(n)
+            })
+    }
+
+    /// Write the trace as text, one state per line: the IP and then the
+    /// registers. Faults aren't saved.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for s in &self.states {
+            write_state(w, s)?;
+        }
+        Ok(())
+    }
+
+    /// Read a trace written by `write_to`.
+    pub fn read_from<R: BufRead>(r: R) -> io::Result<Trace> {
+        let mut states = Vec::new();
+        for (i, l) in r.lines().enumerate() {
+            states.push(parse_state(i, &l?)?);
+        }
+        Ok(Trace { states })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_to(&mut w)?;
+        w.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Trace> {
+        Trace::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+fn write_state<W: Write>(w: &mut W, s: &Snapshot) -> io::Result<()> {
+    write!(w, "{}", s.ip)?;
+    for r in &s.reg {
+        write!(w, " {}", r)?;
+    }
+    writeln!(w)
+}
+
+/// Parse line `i`, counting from 0, of a saved trace.
+fn parse_state(i: usize, l: &str) -> io::Result<Snapshot> {
+    let nums = l
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<usize>, _>>()
+        .ok()
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad trace line {}: {:?}", i + 1, l),
+            )
+        })?;
+    Ok(Snapshot {
+        ip: nums[0],
+        reg: nums[1..].to_vec(),
+        fault: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn day21(r0: Reg) -> Program {
+        let mut p: Program = include_str!("../../input/input21.txt").parse().unwrap();
+        p.reg[0] = r0;
+        p
+    }
+
+    #[test]
+    fn rewind() {
+        let mut p = day21(0);
+        for _ in 0..50 {
+            p.step();
+        }
+        let check = p.snapshot();
+        for _ in 0..1000 {
+            p.step();
+        }
+        let later = p.snapshot();
+        assert_ne!(check, later);
+        p.restore(&check);
+        assert_eq!(p.snapshot(), check);
+        for _ in 0..1000 {
+            p.step();
+        }
+        assert_eq!(p.snapshot(), later);
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let mut p = day21(0);
+        let trace = Trace::record(&mut p, 500);
+        assert_eq!(trace.states.len(), 501);
+        assert_eq!(trace.states[500], p.snapshot());
+
+        let path = std::env::temp_dir().join(format!("elftrace-{}.txt", std::process::id()));
+        trace.save(&path).unwrap();
+        let loaded = Trace::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, trace);
+
+        let mut p = day21(0);
+        assert_eq!(loaded.replay(&mut p), Ok(()));
+        // Replay starts from the recorded state, whatever the program's
+        // state was.
+        let mut p = day21(1);
+        assert_eq!(loaded.replay(&mut p), Ok(()));
+        let mut bad = loaded.clone();
+        bad.states[10].reg[3] += 1;
+        assert_eq!(bad.replay(&mut p), Err(10));
+    }
+
+    #[test]
+    fn diverge_day21() {
+        // 202209 halts at the first check; 0 doesn't, so they part at the
+        // jump after the comparison.
+        let a = Trace::record(&mut day21(0), 100_000);
+        let b = Trace::record(&mut day21(202209), 100_000);
+        let i = a.first_divergence(&b).unwrap();
+        assert_eq!(a.states[i - 1].ip, 29);
+        assert_eq!(b.states[i].ip, 31);
+        assert_eq!(b.states.len(), i + 1);
+        assert_eq!(a.first_divergence(&a), None);
+
+        // The same, streaming.
+        let mut saved = Vec::new();
+        assert_eq!(
+            Trace::record_to(&mut day21(0), 100_000, &mut saved).unwrap(),
+            100_000
+        );
+        let mut expected = Vec::new();
+        a.write_to(&mut expected).unwrap();
+        assert_eq!(saved, expected);
+        let mut p = day21(202209);
+        assert_eq!(
+            Trace::diverge_from(&mut p, 100_000, saved.as_slice()).unwrap(),
+            Some(i)
+        );
+        assert!(p.is_halted());
+        assert_eq!(
+            Trace::diverge_from(&mut day21(0), 100_000, saved.as_slice()).unwrap(),
+            None
+        );
+        assert_eq!(
+            Trace::diverge_from(&mut day21(0), 200_000, saved.as_slice()).unwrap(),
+            Some(100_001)
+        );
+    }
+
+    #[test]
+    fn record_halted() {
+        let mut p: Program = "#ip 0\nseti 5 0 1\n".parse().unwrap();
+        assert_eq!(Trace::record(&mut p, 10).states.len(), 2);
+        // Already halted, so there's only the starting state.
+        assert_eq!(Trace::record(&mut p, 10).states.len(), 1);
+        let mut saved = Vec::new();
+        assert_eq!(Trace::record_to(&mut p, 10, &mut saved).unwrap(), 0);
+        assert_eq!(saved, b"1 0 5 0 0 0 0\n");
+    }
+
+    #[test]
+    fn bad_trace_file() {
+        let err = Trace::read_from("0 1 2\n\n".as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "bad trace line 2: \"\"");
+    }
+}
@@ -0,0 +1,366 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Run ElfCode on symbolic register values.
+//!
+//! Starting from some address, each register holds an expression in terms
+//! of the registers' values at the start. Instructions build up and
+//! simplify those expressions, and jumps are followed as long as their
+//! targets are constant. Execution stops at the first loop, or a jump that
+//! depends on something unknown, so the result describes one straight-line
+//! path through the program.
+//!
+//! For example, running day 19's setup with r0 = 1 shows that it leaves
+//! `r3` holding a constant, which is the number whose divisors are summed.
+
+use std::fmt;
+
+use super::{FaultKind, Opcode, Operand, Program, Reg};
+
+/// An operation on two expressions.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Mul,
+    And,
+    Or,
+    Gt,
+    Eq,
+}
+
+impl BinOp {
+    fn of(opcode: Opcode) -> Option<BinOp> {
+        use super::Opcode::*;
+        Some(match opcode {
+            Addr | Addi => BinOp::Add,
+            Mulr | Muli => BinOp::Mul,
+            Banr | Bani => BinOp::And,
+            Borr | Bori => BinOp::Or,
+            Gtir | Gtri | Gtrr => BinOp::Gt,
+            Eqir | Eqri | Eqrr => BinOp::Eq,
+            Setr | Seti => return None,
+        })
+    }
+
+    fn fold(self, x: Reg, y: Reg) -> Option<Reg> {
+        match self {
+            BinOp::Add => x.checked_add(y),
+            BinOp::Mul => x.checked_mul(y),
+            BinOp::And => Some(x & y),
+            BinOp::Or => Some(x | y),
+            BinOp::Gt => Some((x > y) as Reg),
+            BinOp::Eq => Some((x == y) as Reg),
+        }
+    }
+
+    fn is_commutative(self) -> bool {
+        self != BinOp::Gt
+    }
+
+    /// Binding strength, as in Rust.
+    fn precedence(self) -> u32 {
+        match self {
+            BinOp::Mul => 5,
+            BinOp::Add => 4,
+            BinOp::And => 3,
+            BinOp::Or => 2,
+            BinOp::Gt | BinOp::Eq => 1,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Mul => "*",
+            BinOp::And => "&",
+            BinOp::Or => "|",
+            BinOp::Gt => ">",
+            BinOp::Eq => "==",
+        }
+    }
+}
+
+/// A register value in terms of the registers at the start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Const(Reg),
+    /// The value register N had at the start.
+    Input(usize),
+    Bin(BinOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Combine two expressions, simplifying where possible.
+    pub fn bin(op: BinOp, a: Expr, b: Expr) -> Expr {
+        use self::Expr::*;
+        // Keep constants on the right, so they can be combined below.
+        let (a, b) = match (a, b) {
+            (Const(x), b) if op.is_commutative() && b.value().is_none() => (b, Const(x)),
+            ab => ab,
+        };
+        match (op, &a, &b) {
+            (_, Const(x), Const(y)) => {
+                if let Some(v) = op.fold(*x, *y) {
+                    return Const(v);
+                }
+            }
+            (BinOp::Add, _, Const(0)) | (BinOp::Mul, _, Const(1)) | (BinOp::Or, _, Const(0)) => {
+                return a
+            }
+            (BinOp::Mul, _, Const(0)) | (BinOp::And, _, Const(0)) => return Const(0),
+            (BinOp::Eq, _, _) if a == b => return Const(1),
+            (BinOp::Gt, _, _) if a == b => return Const(0),
+            (BinOp::Add, Bin(BinOp::Add, x, c1), Const(c2))
+            | (BinOp::Mul, Bin(BinOp::Mul, x, c1), Const(c2)) => {
+                if let Some(c) = c1.value().and_then(|c1| op.fold(c1, *c2)) {
+                    return Expr::bin(op, (**x).clone(), Const(c));
+                }
+            }
+            // Float constants out to the right: a + (y + c) = (a + y) + c.
+            (BinOp::Add, _, Bin(BinOp::Add, y, c)) | (BinOp::Mul, _, Bin(BinOp::Mul, y, c))
+                if c.value().is_some() =>
+            {
+                return Expr::bin(op, Expr::bin(op, a.clone(), (**y).clone()), (**c).clone());
+            }
+            _ => {}
+        }
+        Bin(op, Box::new(a), Box::new(b))
+    }
+
+    /// The value, if it's a constant.
+    pub fn value(&self) -> Option<Reg> {
+        match self {
+            Expr::Const(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Evaluate with native arithmetic, given the starting register
+    /// values, or fail if it overflows or needs an input that's missing.
+    pub fn eval(&self, inputs: &[Reg]) -> Result<Reg, FaultKind> {
+        match self {
+            Expr::Const(v) => Ok(*v),
+            Expr::Input(r) => inputs.get(*r).cloned().ok_or(FaultKind::BadRegister(*r)),
+            Expr::Bin(op, a, b) => op
+                .fold(a.eval(inputs)?, b.eval(inputs)?)
+                .ok_or(FaultKind::Overflow),
+        }
+    }
+
+    fn precedence(&self) -> u32 {
+        match self {
+            Expr::Bin(op, _, _) => op.precedence(),
+            _ => u32::MAX,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(v) => write!(f, "{}", v),
+            Expr::Input(r) => write!(f, "r{}", r),
+            Expr::Bin(op, a, b) => {
+                // Operators are left-associative, except that comparisons
+                // don't chain at all.
+                let p = op.precedence();
+                let left_parens = a.precedence() < p || (p == 1 && a.precedence() == 1);
+                let right_parens = b.precedence() <= p;
+                let side = |f: &mut fmt::Formatter, e: &Expr, parens| {
+                    if parens {
+                        write!(f, "({})", e)
+                    } else {
+                        write!(f, "{}", e)
+                    }
+                };
+                side(f, a, left_parens)?;
+                write!(f, " {} ", op.symbol())?;
+                side(f, b, right_parens)
+            }
+        }
+    }
+}
+
+/// Why symbolic execution stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum End {
+    /// The IP left the program.
+    Halted,
+    /// About to run an instruction that already ran, so this is a loop.
+    Loop,
+    /// The IP register holds this non-constant expression.
+    UnknownJump(Expr),
+    /// An instruction, or the `#ip` directive, names a register that
+    /// doesn't exist.
+    BadRegister(usize),
+}
+
+/// The result of running a region symbolically.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    /// Addresses executed, in order.
+    pub path: Vec<usize>,
+    /// The next address that would run. For an unknown jump, this is the
+    /// address of the jump.
+    pub ip: usize,
+    /// Register values at the end.
+    pub reg: Vec<Expr>,
+    pub end: End,
+}
+
+/// Every register holding its own starting value.
+pub fn inputs(nreg: usize) -> Vec<Expr> {
+    (0..nreg).map(Expr::Input).collect()
+}
+
+/// Run symbolically from `start` with the given register values, until
+/// something stops the straight-line path.
+pub fn run_region(prog: &Program, start: usize, mut reg: Vec<Expr>) -> Region {
+    let mut path: Vec<usize> = Vec::new();
+    let mut ip = start;
+    let end = loop {
+        if ip >= prog.code.len() {
+            break End::Halted;
+        }
+        if path.contains(&ip) {
+            break End::Loop;
+        }
+        if let Some(ip_reg) = prog.ip_reg {
+            match reg.get_mut(ip_reg) {
+                Some(r) => *r = Expr::Const(ip),
+                None => break End::BadRegister(ip_reg),
+            }
+        }
+        path.push(ip);
+        let inst = prog.code[ip];
+        let (oa, ob) = inst.opcode.operands();
+        let arg = |o, x: Reg| match o {
+            Operand::Reg => reg.get(x).cloned().ok_or(End::BadRegister(x)),
+            _ => Ok(Expr::Const(x)),
+        };
+        let value = match (arg(oa, inst.a), arg(ob, inst.b)) {
+            (Ok(a), Ok(b)) => match BinOp::of(inst.opcode) {
+                Some(op) => Expr::bin(op, a, b),
+                None => a,
+            },
+            (Err(e), _) | (_, Err(e)) => break e,
+        };
+        match reg.get_mut(inst.c) {
+            Some(r) => *r = value,
+            None => break End::BadRegister(inst.c),
+        }
+        if let Some(ip_reg) = prog.ip_reg {
+            match reg[ip_reg].value() {
+                Some(v) => ip = v,
+                None => {
+                    let target = reg[ip_reg].clone();
+                    // Show the IP register as it was before the jump.
+                    reg[ip_reg] = Expr::Const(ip);
+                    break End::UnknownJump(target);
+                }
+            }
+        }
+        ip += 1;
+    };
+    Region { path, ip, reg, end }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn day19() -> Program {
+        include_str!("../../input/input19.txt").parse().unwrap()
+    }
+
+    #[test]
+    fn simplify() {
+        use self::Expr::*;
+        let r1 = || Input(1);
+        let e = Expr::bin(BinOp::Add, Expr::bin(BinOp::Add, Const(3), r1()), Const(4));
+        assert_eq!(e.to_string(), "r1 + 7");
+        assert_eq!(Expr::bin(BinOp::Mul, r1(), Const(0)), Const(0));
+        assert_eq!(Expr::bin(BinOp::Or, Const(0), r1()), r1());
+        assert_eq!(Expr::bin(BinOp::Eq, r1(), r1()), Const(1));
+        assert_eq!(Expr::bin(BinOp::Gt, Const(2), Const(1)), Const(1));
+        let sum = Expr::bin(BinOp::Add, r1(), Input(2));
+        let e = Expr::bin(BinOp::Mul, sum.clone(), Expr::bin(BinOp::Gt, sum, Const(9)));
+        assert_eq!(e.to_string(), "(r1 + r2) * (r1 + r2 > 9)");
+    }
+
+    #[test]
+    fn day19_setup() {
+        let p = day19();
+        // With r0 unknown, the setup stops at the jump that depends on it.
+        let region = run_region(&p, 0, inputs(6));
+        assert_eq!(region.path, vec![0, 17, 18, 19, 20, 21, 22, 23, 24, 25]);
+        assert_eq!(region.ip, 25);
+        assert_eq!(
+            region.end,
+            End::UnknownJump(Expr::bin(BinOp::Add, Expr::Input(0), Expr::Const(25)))
+        );
+        assert_eq!(
+            region.reg[3].to_string(),
+            "(r3 + 2) * (r3 + 2) * 209 + (r4 + 8) * 22 + 13"
+        );
+    }
+
+    #[test]
+    fn day19_magic_constant() {
+        // Part two starts with r0 = 1, which takes the longer setup path, and
+        // then stops at the first test in the divisor loop.
+        let p = day19();
+        let mut reg = inputs(6);
+        reg[0] = Expr::Const(1);
+        let region = run_region(&p, 0, reg);
+        assert_eq!(region.ip, 5);
+        assert_eq!(
+            region.reg[3].to_string(),
+            "(r3 + 2) * (r3 + 2) * 209 + (r4 + 8) * 22 + 10550413"
+        );
+        // Everything starts at 0.
+        assert_eq!(region.reg[3].eval(&[0; 6]), Ok(10551425));
+        assert_eq!(
+            region.reg[3].eval(&[0, 0, 0, Reg::MAX, 0, 0]),
+            Err(FaultKind::Overflow)
+        );
+        assert_eq!(region.reg[3].eval(&[0; 3]), Err(FaultKind::BadRegister(3)));
+        match region.end {
+            End::UnknownJump(e) => assert_eq!(
+                e.to_string(),
+                "((r3 + 2) * (r3 + 2) * 209 + (r4 + 8) * 22 + 10550413 == 1) + 5"
+            ),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn concrete_loop() {
+        let p = day19();
+        let mut reg = vec![Expr::Const(0); 6];
+        reg[0] = Expr::Const(1);
+        let region = run_region(&p, 0, reg);
+        assert_eq!(region.end, End::Loop);
+        assert_eq!(region.ip, 3);
+        assert_eq!(region.reg[3], Expr::Const(10551425));
+    }
+
+    #[test]
+    fn too_few_registers() {
+        let p = day19();
+        let region = run_region(&p, 0, inputs(3));
+        assert_eq!(region.end, End::BadRegister(5));
+        assert!(region.path.is_empty());
+    }
+}
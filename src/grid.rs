@@ -0,0 +1,333 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A 2D grid with no fixed bounds, that grows to fit whatever is written.
+//!
+//! ```
+//! use aoc2018::{ipoint, Grid};
+//!
+//! let mut g = Grid::new('.');
+//! g[ipoint(-3, 2)] = '#';
+//! g[ipoint(4, -1)] = '#';
+//! assert_eq!(g[ipoint(-3, 2)], '#');
+//! // Reading outside what's been written gives the default.
+//! assert_eq!(g[ipoint(100, 100)], '.');
+//! assert_eq!(g.x_range(), -3..5);
+//! assert_eq!(g.y_range(), -1..3);
+//! ```
+//!
+//! Storage is a dense rectangle covering everything written, so this suits
+//! areas that are mostly filled in. For a few points scattered far apart,
+//! use a map or set of `IPoint` instead.
+
+use std::ops::{Index, IndexMut, Range};
+
+use crate::{ipoint, point, IPoint, Matrix};
+
+/// The most cells a grid will allocate, to fail clearly on a stray write
+/// far outside the area in use rather than exhausting memory.
+pub const MAX_CELLS: usize = 1 << 28;
+
+#[derive(Clone, Debug)]
+pub struct Grid<T> {
+    /// Coordinates of the first allocated cell.
+    x0: isize,
+    y0: isize,
+    /// Allocated size, which may be larger than the written bounds.
+    w: usize,
+    h: usize,
+    d: Vec<T>,
+    /// Smallest and largest points written, if any.
+    bounds: Option<(IPoint, IPoint)>,
+    /// Value of cells that haven't been written.
+    default: T,
+}
+
+impl<T: Clone> Grid<T> {
+    /// Make an empty grid, where every cell reads as `default`.
+    pub fn new(default: T) -> Grid<T> {
+        Grid {
+            x0: 0,
+            y0: 0,
+            w: 0,
+            h: 0,
+            d: Vec::new(),
+            bounds: None,
+            default,
+        }
+    }
+
+    /// True if nothing has been written.
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_none()
+    }
+
+    /// The x coordinates of the smallest rectangle holding everything
+    /// written.
+    pub fn x_range(&self) -> Range<isize> {
+        self.bounds.map_or(0..0, |(lo, hi)| lo.x..(hi.x + 1))
+    }
+
+    /// The y coordinates of the smallest rectangle holding everything
+    /// written.
+    pub fn y_range(&self) -> Range<isize> {
+        self.bounds.map_or(0..0, |(lo, hi)| lo.y..(hi.y + 1))
+    }
+
+    pub fn width(&self) -> usize {
+        self.x_range().len()
+    }
+
+    pub fn height(&self) -> usize {
+        self.y_range().len()
+    }
+
+    /// True if `p` is within the bounds of what's been written.
+    pub fn contains(&self, p: IPoint) -> bool {
+        self.x_range().contains(&p.x) && self.y_range().contains(&p.y)
+    }
+
+    /// The value at `p`, or None if it's outside the bounds.
+    pub fn get(&self, p: IPoint) -> Option<&T> {
+        if self.contains(p) {
+            Some(&self[p])
+        } else {
+            None
+        }
+    }
+
+    /// All points within the bounds, in reading order.
+    pub fn iter_points<'a>(&'a self) -> Box<dyn Iterator<Item = IPoint> + 'a> {
+        let xs = self.x_range();
+        Box::new(
+            self.y_range()
+                .flat_map(move |y| xs.clone().map(move |x| ipoint(x, y))),
+        )
+    }
+
+    /// Copy the area within the bounds into a `Matrix`, with the top-left
+    /// corner at `point(0, 0)`.
+    pub fn to_matrix(&self) -> Matrix<T> {
+        let mut m = Matrix::new(self.width(), self.height(), self.default.clone());
+        let (x0, y0) = (self.x_range().start, self.y_range().start);
+        for p in self.iter_points() {
+            m[point((p.x - x0) as usize, (p.y - y0) as usize)] = self[p].clone();
+        }
+        m
+    }
+
+    fn offset(&self, p: IPoint) -> Option<usize> {
+        let x = p.x.checked_sub(self.x0)?;
+        let y = p.y.checked_sub(self.y0)?;
+        if x >= 0 && y >= 0 && (x as usize) < self.w && (y as usize) < self.h {
+            Some(y as usize * self.w + x as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Make sure there's space allocated for `p`.
+    ///
+    /// The allocation at least doubles in each direction it grows, so a run
+    /// of writes moving steadily outwards takes amortized constant time.
+    ///
+    /// Storage is dense, so this allocates the whole rectangle spanning
+    /// everything written: a single write far from the rest costs memory
+    /// proportional to the area between them. Panics rather than trying to
+    /// allocate more than `MAX_CELLS`.
+    fn reserve(&mut self, p: IPoint) {
+        if self.offset(p).is_some() {
+            return;
+        }
+        // Grow to include `c`, leaving `pad` spare cells beyond it.
+        let grow = |lo: isize, len: usize, c: isize, pad: usize| -> Option<(isize, usize)> {
+            let pad = pad as isize;
+            let hi = lo + len as isize;
+            let (new_lo, new_hi) = if len == 0 {
+                (c.checked_sub(pad / 2)?, c.checked_add(pad / 2 + 1)?)
+            } else if c < lo {
+                (c.checked_sub(pad)?, hi)
+            } else if c >= hi {
+                (lo, c.checked_add(1 + pad)?)
+            } else {
+                (lo, hi)
+            };
+            Some((new_lo, new_hi.checked_sub(new_lo)? as usize))
+        };
+        let fits = |pad_x: usize, pad_y: usize| {
+            let (x0, w) = grow(self.x0, self.w, p.x, pad_x)?;
+            let (y0, h) = grow(self.y0, self.h, p.y, pad_y)?;
+            w.checked_mul(h)
+                .filter(|n| *n <= MAX_CELLS)
+                .map(|_| (x0, w, y0, h))
+        };
+        // Near the limit, give up on spare room before giving up entirely.
+        let (x0, w, y0, h) = fits(self.w.max(4), self.h.max(4))
+            .or_else(|| fits(0, 0))
+            .unwrap_or_else(|| {
+                panic!(
+                    "writing {:?} would grow the grid past {} cells",
+                    p, MAX_CELLS
+                )
+            });
+        let mut d = vec![self.default.clone(); w * h];
+        for y in 0..self.h {
+            let old = y * self.w;
+            let new = (y + (self.y0 - y0) as usize) * w + (self.x0 - x0) as usize;
+            d[new..(new + self.w)].clone_from_slice(&self.d[old..(old + self.w)]);
+        }
+        self.x0 = x0;
+        self.y0 = y0;
+        self.w = w;
+        self.h = h;
+        self.d = d;
+    }
+}
+
+/// Grids are equal if every cell reads the same, regardless of how much
+/// space is allocated or which cells were written with the default value.
+impl<T: Clone + PartialEq> PartialEq for Grid<T> {
+    fn eq(&self, other: &Grid<T>) -> bool {
+        if self.default != other.default {
+            return false;
+        }
+        let span = |a: Range<isize>, b: Range<isize>| {
+            if a.is_empty() {
+                b
+            } else if b.is_empty() {
+                a
+            } else {
+                a.start.min(b.start)..a.end.max(b.end)
+            }
+        };
+        let xs = span(self.x_range(), other.x_range());
+        span(self.y_range(), other.y_range()).all(|y| {
+            xs.clone()
+                .all(|x| self[ipoint(x, y)] == other[ipoint(x, y)])
+        })
+    }
+}
+
+impl<T: Clone> Index<IPoint> for Grid<T> {
+    type Output = T;
+
+    /// The value at `p`, or the default if nothing was written there.
+    fn index(&self, p: IPoint) -> &T {
+        match self.offset(p) {
+            Some(i) => &self.d[i],
+            None => &self.default,
+        }
+    }
+}
+
+impl<T: Clone> IndexMut<IPoint> for Grid<T> {
+    /// A mutable reference to `p`, growing the grid to include it.
+    fn index_mut(&mut self, p: IPoint) -> &mut T {
+        self.reserve(p);
+        self.bounds = Some(match self.bounds {
+            None => (p, p),
+            Some((lo, hi)) => (
+                ipoint(lo.x.min(p.x), lo.y.min(p.y)),
+                ipoint(hi.x.max(p.x), hi.y.max(p.y)),
+            ),
+        });
+        let i = self.offset(p).unwrap();
+        &mut self.d[i]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        let g = Grid::new(0u8);
+        assert!(g.is_empty());
+        assert_eq!((g.width(), g.height()), (0, 0));
+        assert_eq!(g[ipoint(-5, 5)], 0);
+        assert_eq!(g.get(ipoint(0, 0)), None);
+        assert_eq!(g.iter_points().count(), 0);
+    }
+
+    #[test]
+    fn grows_in_every_direction() {
+        let mut g = Grid::new(0);
+        // Spiral outwards, so the grid has to grow on every side many times.
+        let mut p = ipoint(0, 0);
+        let mut written = Vec::new();
+        for i in 1..200 {
+            for _ in 0..(i / 2) {
+                p = match i % 4 {
+                    0 => p.right(),
+                    1 => p.down(),
+                    2 => p.left(),
+                    _ => p.up(),
+                };
+                g[p] += i;
+                written.push((p, g[p]));
+            }
+        }
+        for (p, v) in written {
+            assert_eq!(g[p], v);
+        }
+        assert_eq!(g.x_range(), -50..50);
+        assert_eq!(g.y_range(), -50..50);
+        assert_eq!(g.iter_points().count(), g.width() * g.height());
+    }
+
+    #[test]
+    fn matrix() {
+        let mut g = Grid::new('.');
+        g[ipoint(-1, -1)] = 'a';
+        g[ipoint(1, 0)] = 'b';
+        assert_eq!(g.get(ipoint(0, 0)), Some(&'.'));
+        assert_eq!(g.get(ipoint(2, 0)), None);
+        let m = g.to_matrix();
+        assert_eq!((m.width(), m.height()), (3, 2));
+        assert_eq!(m.values().collect::<String>(), "a....b");
+        assert_eq!(
+            g.iter_points().collect::<Vec<_>>(),
+            vec![
+                ipoint(-1, -1),
+                ipoint(0, -1),
+                ipoint(1, -1),
+                ipoint(-1, 0),
+                ipoint(0, 0),
+                ipoint(1, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn equality() {
+        let mut a = Grid::new(0);
+        let mut b = Grid::new(0);
+        a[ipoint(2, 2)] = 1;
+        b[ipoint(-20, 30)] = 0;
+        b[ipoint(2, 2)] = 1;
+        assert_eq!(a, b);
+        b[ipoint(-20, 30)] = 2;
+        assert_ne!(a, b);
+        assert_ne!(Grid::new(0), Grid::new(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "would grow the grid past")]
+    fn far_write() {
+        let mut g = Grid::new(0u8);
+        g[ipoint(0, 0)] = 1;
+        g[ipoint(1 << 20, 1 << 20)] = 1;
+    }
+}
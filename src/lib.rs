@@ -14,12 +14,22 @@
 
 //! mbp AoC2018 solutions - general utilities.
 
+pub mod automaton;
 pub mod bisection_search;
+pub mod elfcode;
+pub mod grid;
 pub mod matrix;
 mod point;
 mod shortest_path;
+pub mod summed_area;
 
+pub use crate::automaton::{Automaton, Tape};
 pub use crate::bisection_search::bisection_search;
-pub use crate::matrix::Matrix;
-pub use crate::point::{point, Point};
-pub use crate::shortest_path::shortest_distance;
+pub use crate::grid::Grid;
+pub use crate::matrix::{Glyph, Matrix};
+pub use crate::point::{ipoint, point, IPoint, Point};
+pub use crate::shortest_path::{
+    all_distances, astar_path, astar_path_with, bounded_shortest_path, nearest, nearest_of,
+    shortest_distance, shortest_path, DenseTable, DistanceTable, Limits, Route, SearchError,
+};
+pub use crate::summed_area::SummedArea;
@@ -15,11 +15,71 @@
 //! A rectangular 2d matrix.
 //!
 //! Matrices are indexed by (row, column) coordinates.
+use std::fmt;
 use std::ops::{Index, IndexMut};
 
 use crate::{point, Point};
 
-#[derive(Clone, Eq, PartialEq)]
+/// Something that's drawn as a single character in puzzle maps.
+pub trait Glyph: Sized {
+    /// The value for a character, or None if it's not a valid glyph.
+    fn from_char(c: char) -> Option<Self>;
+
+    fn to_char(&self) -> char;
+}
+
+impl Glyph for char {
+    fn from_char(c: char) -> Option<char> {
+        Some(c)
+    }
+
+    fn to_char(&self) -> char {
+        *self
+    }
+}
+
+/// A problem parsing a map drawn in characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsciiError {
+    /// A character that isn't a valid glyph, at a 1-based line and column.
+    BadChar { line: usize, col: usize, ch: char },
+    /// A line whose length differs from the first line.
+    Ragged {
+        line: usize,
+        len: usize,
+        expected: usize,
+    },
+    /// There were no lines, or the first line was empty.
+    Empty,
+}
+
+impl fmt::Display for AsciiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsciiError::BadChar { line, col, ch } => {
+                write!(
+                    f,
+                    "line {}, column {}: unexpected character {:?}",
+                    line, col, ch
+                )
+            }
+            AsciiError::Ragged {
+                line,
+                len,
+                expected,
+            } => write!(
+                f,
+                "line {}: length {} differs from first line's {}",
+                line, len, expected
+            ),
+            AsciiError::Empty => write!(f, "empty map: the first line has no characters"),
+        }
+    }
+}
+
+impl std::error::Error for AsciiError {}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Matrix<T> {
     w: usize,
     h: usize,
@@ -56,56 +116,342 @@ impl<T: Clone> Matrix<T> {
         self.d.iter()
     }
 
+    /// True if `p` is within the matrix.
+    pub fn contains(&self, p: Point) -> bool {
+        p.x < self.w && p.y < self.h
+    }
+
+    /// The value at `p`, or None if it's outside the matrix.
+    pub fn get(&self, p: Point) -> Option<&T> {
+        if self.contains(p) {
+            Some(&self.d[self.w * p.y + p.x])
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, p: Point) -> Option<&mut T> {
+        if self.contains(p) {
+            Some(&mut self.d[self.w * p.y + p.x])
+        } else {
+            None
+        }
+    }
+
+    /// The orthogonal neighbors of `p` that are within the matrix, in
+    /// reading order.
+    pub fn neighbors4(&self, p: Point) -> Vec<Point> {
+        [
+            p.checked_up(),
+            p.checked_left(),
+            p.checked_right(),
+            p.checked_down(),
+        ]
+        .iter()
+        .flatten()
+        .filter(|n| self.contains(**n))
+        .cloned()
+        .collect()
+    }
+
+    /// The orthogonal and diagonal neighbors of `p` that are within the
+    /// matrix, in reading order.
+    pub fn neighbors8(&self, p: Point) -> Vec<Point> {
+        let mut v = Vec::with_capacity(8);
+        for y in p.y.saturating_sub(1)..=(p.y + 1) {
+            for x in p.x.saturating_sub(1)..=(p.x + 1) {
+                let n = point(x, y);
+                if n != p && self.contains(n) {
+                    v.push(n);
+                }
+            }
+        }
+        v
+    }
+
     /// Return a vec of all present 8-way neighbors.
     pub fn neighbor8_values(&self, p: Point) -> Vec<T> {
-        let mut v: Vec<T> = Vec::with_capacity(8);
-        if p.y > 0 {
-            if p.x > 0 {
-                v.push(self[p.left().up()].clone())
-            }
-            v.push(self[p.up()].clone());
-            if p.x < self.w - 1 {
-                v.push(self[p.right().up()].clone())
+        self.neighbors8(p)
+            .into_iter()
+            .map(|n| self[n].clone())
+            .collect()
+    }
+
+    pub fn iter_points<'a>(&'a self) -> Box<dyn Iterator<Item = Point> + 'a> {
+        Box::new((0..self.h).flat_map(move |y| (0..self.w).map(move |x| point(x, y))))
+    }
+
+    /// Make a matrix by calling `f` for every point, in reading order.
+    pub fn from_fn(w: usize, h: usize, mut f: impl FnMut(Point) -> T) -> Matrix<T> {
+        let mut d = Vec::with_capacity(w * h);
+        for y in 0..h {
+            for x in 0..w {
+                d.push(f(point(x, y)));
             }
         }
-        if p.x > 0 {
-            v.push(self[p.left()].clone())
+        Matrix { w, h, d }
+    }
+
+    /// The values in row `y`, from left to right.
+    pub fn row(&self, y: usize) -> &[T] {
+        assert!(y < self.h, "row {} out of range for height {}", y, self.h);
+        &self.d[(y * self.w)..((y + 1) * self.w)]
+    }
+
+    /// All rows, from top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        (0..self.h).map(move |y| self.row(y))
+    }
+
+    /// All rows, mutably.
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [T]> {
+        // `chunks_mut` panics on a width of 0, and would yield no rows
+        // rather than `h` empty ones.
+        let empty_rows = if self.w == 0 { self.h } else { 0 };
+        self.d
+            .chunks_mut(self.w.max(1))
+            .chain(std::iter::repeat_with(|| &mut [][..]).take(empty_rows))
+    }
+
+    /// The values in column `x`, from top to bottom.
+    pub fn column(&self, x: usize) -> impl Iterator<Item = &T> {
+        assert!(x < self.w, "column {} out of range for width {}", x, self.w);
+        self.d.iter().skip(x).step_by(self.w)
+    }
+
+    /// All columns, from left to right.
+    pub fn columns(&self) -> impl Iterator<Item = impl Iterator<Item = &T>> {
+        (0..self.w).map(move |x| self.column(x))
+    }
+
+    /// A read-only view of the `w` by `h` rectangle whose top-left corner
+    /// is at `origin`.
+    pub fn view(&self, origin: Point, w: usize, h: usize) -> View<'_, T> {
+        assert!(
+            origin.x + w <= self.w && origin.y + h <= self.h,
+            "{}x{} view at {:?} doesn't fit in {}x{}",
+            w,
+            h,
+            origin,
+            self.w,
+            self.h
+        );
+        View {
+            m: self,
+            origin,
+            w,
+            h,
+        }
+    }
+
+    /// A new matrix of the same shape, with `f` applied to every value.
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Matrix<U> {
+        Matrix {
+            w: self.w,
+            h: self.h,
+            d: self.d.iter().map(f).collect(),
         }
-        if p.x < self.w - 1 {
-            v.push(self[p.right()].clone())
+    }
+
+    /// Combine corresponding values from two matrices of the same shape.
+    pub fn zip_with<U, V>(&self, other: &Matrix<U>, mut f: impl FnMut(&T, &U) -> V) -> Matrix<V> {
+        assert_eq!(
+            (self.w, self.h),
+            (other.w, other.h),
+            "matrices are different shapes"
+        );
+        Matrix {
+            w: self.w,
+            h: self.h,
+            d: self.d.iter().zip(&other.d).map(|(a, b)| f(a, b)).collect(),
         }
-        if p.y < self.h - 1 {
-            if p.x > 0 {
-                v.push(self[p.left().down()].clone())
+    }
+
+    /// Swap rows and columns.
+    pub fn transpose(&self) -> Matrix<T> {
+        Matrix::from_fn(self.h, self.w, |p| self[point(p.y, p.x)].clone())
+    }
+
+    /// Rotate a quarter turn clockwise.
+    pub fn rotate_cw(&self) -> Matrix<T> {
+        Matrix::from_fn(self.h, self.w, |p| {
+            self[point(p.y, self.h - 1 - p.x)].clone()
+        })
+    }
+
+    /// Rotate a quarter turn anticlockwise.
+    pub fn rotate_ccw(&self) -> Matrix<T> {
+        Matrix::from_fn(self.h, self.w, |p| {
+            self[point(self.w - 1 - p.y, p.x)].clone()
+        })
+    }
+
+    /// Mirror left to right.
+    pub fn flip_h(&self) -> Matrix<T> {
+        Matrix::from_fn(self.w, self.h, |p| {
+            self[point(self.w - 1 - p.x, p.y)].clone()
+        })
+    }
+
+    /// Mirror top to bottom.
+    pub fn flip_v(&self) -> Matrix<T> {
+        Matrix::from_fn(self.w, self.h, |p| {
+            self[point(p.x, self.h - 1 - p.y)].clone()
+        })
+    }
+
+    /// The first point, in reading order, whose value matches.
+    pub fn find(&self, pred: impl FnMut(&T) -> bool) -> Option<Point> {
+        self.d
+            .iter()
+            .position(pred)
+            .map(|i| point(i % self.w, i / self.w))
+    }
+
+    /// The number of values that match.
+    pub fn count(&self, mut pred: impl FnMut(&T) -> bool) -> usize {
+        self.d.iter().filter(|v| pred(v)).count()
+    }
+}
+
+impl<T: Clone + Glyph> Matrix<T> {
+    /// Parse a map with one row per line, and one glyph per character.
+    ///
+    /// Blank lines at the end are ignored; any other line must be as long as
+    /// the first.
+    pub fn parse_ascii(s: &str) -> Result<Matrix<T>, AsciiError> {
+        let mut b = Matrix::from_rows();
+        let lines: Vec<&str> = s.trim_end_matches(['\n', '\r']).lines().collect();
+        let mut expected = None;
+        for (i, l) in lines.iter().enumerate() {
+            let row = l
+                .chars()
+                .enumerate()
+                .map(|(j, ch)| {
+                    T::from_char(ch).ok_or(AsciiError::BadChar {
+                        line: i + 1,
+                        col: j + 1,
+                        ch,
+                    })
+                })
+                .collect::<Result<Vec<T>, AsciiError>>()?;
+            if row.is_empty() && expected.is_none() {
+                return Err(AsciiError::Empty);
             }
-            v.push(self[p.down()].clone());
-            if p.x < self.w - 1 {
-                v.push(self[p.right().down()].clone())
+            let expected = *expected.get_or_insert(row.len());
+            if row.len() != expected {
+                return Err(AsciiError::Ragged {
+                    line: i + 1,
+                    len: row.len(),
+                    expected,
+                });
             }
+            b.add_row(&row);
         }
-        v
+        if lines.is_empty() {
+            return Err(AsciiError::Empty);
+        }
+        Ok(b.finish())
     }
 
-    pub fn iter_points<'a>(&'a self) -> Box<dyn Iterator<Item = Point> + 'a> {
-        Box::new((0..self.h).flat_map(move |y| (0..self.w).map(move |x| point(x, y))))
+    /// Draw the matrix, with a newline after every row.
+    pub fn render_ascii(&self) -> String {
+        let mut s = String::with_capacity(self.h * (self.w + 1));
+        for row in self.rows() {
+            s.extend(row.iter().map(Glyph::to_char));
+            s.push('\n');
+        }
+        s
     }
 }
 
 impl<T: Clone> Index<Point> for Matrix<T> {
     type Output = T;
     fn index(&self, p: Point) -> &T {
+        assert!(
+            self.contains(p),
+            "{:?} outside {}x{} matrix",
+            p,
+            self.w,
+            self.h
+        );
         &self.d[self.w * p.y + p.x]
     }
 }
 
 impl<T: Clone> IndexMut<Point> for Matrix<T> {
     fn index_mut(&mut self, p: Point) -> &mut T {
-        assert!(p.x < self.w, "{:?} too wide for {}", p, self.w);
-        assert!(p.y < self.h);
+        assert!(
+            self.contains(p),
+            "{:?} outside {}x{} matrix",
+            p,
+            self.w,
+            self.h
+        );
         &mut self.d[self.w * p.y + p.x]
     }
 }
 
+/// A rectangular part of a `Matrix`, indexed relative to its own top-left
+/// corner.
+#[derive(Debug)]
+pub struct View<'a, T> {
+    m: &'a Matrix<T>,
+    origin: Point,
+    w: usize,
+    h: usize,
+}
+
+// Derived impls would needlessly require `T: Copy`.
+impl<'a, T> Clone for View<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for View<'a, T> {}
+
+impl<'a, T: Clone> View<'a, T> {
+    pub fn width(&self) -> usize {
+        self.w
+    }
+
+    pub fn height(&self) -> usize {
+        self.h
+    }
+
+    /// The values in row `y` of the view.
+    pub fn row(&self, y: usize) -> &'a [T] {
+        assert!(y < self.h);
+        let start = self.m.w * (self.origin.y + y) + self.origin.x;
+        &self.m.d[start..(start + self.w)]
+    }
+
+    /// All rows of the view, from top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &'a [T]> {
+        let v = *self;
+        (0..self.h).map(move |y| v.row(y))
+    }
+
+    /// All values in row,col order.
+    pub fn values(&self) -> impl Iterator<Item = &'a T> {
+        self.rows().flatten()
+    }
+
+    /// Copy the view into a new matrix.
+    pub fn to_matrix(&self) -> Matrix<T> {
+        Matrix::from_fn(self.w, self.h, |p| self[p].clone())
+    }
+}
+
+impl<'a, T: Clone> Index<Point> for View<'a, T> {
+    type Output = T;
+    fn index(&self, p: Point) -> &T {
+        assert!(p.x < self.w && p.y < self.h, "{:?} outside view", p);
+        &self.m[point(self.origin.x + p.x, self.origin.y + p.y)]
+    }
+}
+
 pub struct FromRows<T> {
     w: usize,
     d: Vec<T>,
@@ -126,7 +472,10 @@ impl<T: Clone> FromRows<T> {
 
     pub fn finish(mut self) -> Matrix<T> {
         self.d.shrink_to_fit();
-        assert!(self.d.len() % self.w == 0, "Matrix isn't rectangular");
+        assert!(
+            self.d.len().is_multiple_of(self.w),
+            "Matrix isn't rectangular"
+        );
         Matrix {
             w: self.w,
             h: self.d.len() / self.w,
@@ -161,4 +510,188 @@ mod test {
         assert_eq!(m[point(2, 0)], 3);
         assert_eq!(m[point(2, 2)], 9);
     }
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    enum Cell {
+        Open,
+        Wall,
+    }
+
+    impl Glyph for Cell {
+        fn from_char(c: char) -> Option<Cell> {
+            match c {
+                '.' => Some(Cell::Open),
+                '#' => Some(Cell::Wall),
+                _ => None,
+            }
+        }
+
+        fn to_char(&self) -> char {
+            match self {
+                Cell::Open => '.',
+                Cell::Wall => '#',
+            }
+        }
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        let s = "#..#\n.##.\n";
+        let m = Matrix::<Cell>::parse_ascii(s).unwrap();
+        assert_eq!((m.width(), m.height()), (4, 2));
+        assert_eq!(m[point(1, 1)], Cell::Wall);
+        assert_eq!(m[point(1, 0)], Cell::Open);
+        assert_eq!(m.render_ascii(), s);
+        let chars = Matrix::<char>::parse_ascii("ab\ncd\n\n").unwrap();
+        assert_eq!(chars.render_ascii(), "ab\ncd\n");
+    }
+
+    #[test]
+    fn ascii_errors() {
+        let e = Matrix::<Cell>::parse_ascii("...\n.x.\n").unwrap_err();
+        assert_eq!(
+            e,
+            AsciiError::BadChar {
+                line: 2,
+                col: 2,
+                ch: 'x'
+            }
+        );
+        assert_eq!(e.to_string(), "line 2, column 2: unexpected character 'x'");
+        assert_eq!(
+            Matrix::<Cell>::parse_ascii("...\n..\n").unwrap_err(),
+            AsciiError::Ragged {
+                line: 2,
+                len: 2,
+                expected: 3
+            }
+        );
+        assert_eq!(
+            Matrix::<Cell>::parse_ascii("").unwrap_err(),
+            AsciiError::Empty
+        );
+        let e = Matrix::<Cell>::parse_ascii("\n...\n").unwrap_err();
+        assert_eq!(e, AsciiError::Empty);
+        assert_eq!(e.to_string(), "empty map: the first line has no characters");
+    }
+
+    #[test]
+    fn zero_width() {
+        let mut m = Matrix::new(0, 3, 'x');
+        assert_eq!(m.rows().count(), 3);
+        assert_eq!(m.rows_mut().count(), 3);
+        assert_eq!(m.render_ascii(), "\n\n\n");
+        assert_eq!(Matrix::new(0, 0, 'x').render_ascii(), "");
+        assert_eq!(Matrix::new(3, 0, 'x').rows_mut().count(), 0);
+    }
+
+    fn numbered(w: usize, h: usize) -> Matrix<usize> {
+        Matrix::from_fn(w, h, |p| p.y * 10 + p.x)
+    }
+
+    #[test]
+    fn rows_and_columns() {
+        let m = numbered(3, 2);
+        assert_eq!(m.row(1), &[10, 11, 12]);
+        assert_eq!(
+            m.rows().collect::<Vec<_>>(),
+            vec![&[0, 1, 2], &[10, 11, 12]]
+        );
+        assert_eq!(m.column(2).cloned().collect::<Vec<_>>(), vec![2, 12]);
+        assert_eq!(
+            m.columns().map(|c| c.sum::<usize>()).collect::<Vec<_>>(),
+            vec![10, 12, 14]
+        );
+    }
+
+    #[test]
+    fn view() {
+        let m = numbered(5, 4);
+        let v = m.view(point(1, 2), 3, 2);
+        assert_eq!((v.width(), v.height()), (3, 2));
+        assert_eq!(v[point(0, 0)], 21);
+        assert_eq!(v[point(2, 1)], 33);
+        assert_eq!(v.row(1), &[31, 32, 33]);
+        assert_eq!(v.values().sum::<usize>(), 21 + 22 + 23 + 31 + 32 + 33);
+        assert_eq!(
+            v.to_matrix(),
+            Matrix::from_fn(3, 2, |p| 21 + p.y * 10 + p.x)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn view_out_of_bounds() {
+        numbered(5, 4).view(point(3, 0), 3, 1);
+    }
+
+    #[test]
+    fn map_and_zip() {
+        let m = numbered(2, 2);
+        let doubled = m.map(|v| v * 2);
+        assert_eq!(
+            doubled.values().cloned().collect::<Vec<_>>(),
+            vec![0, 2, 20, 22]
+        );
+        let diff = doubled.zip_with(&m, |a, b| a - b);
+        assert_eq!(diff, m);
+    }
+
+    #[test]
+    fn rotate_and_flip() {
+        let m = Matrix::<char>::parse_ascii("abc\ndef\n").unwrap();
+        assert_eq!(m.transpose().render_ascii(), "ad\nbe\ncf\n");
+        assert_eq!(m.rotate_cw().render_ascii(), "da\neb\nfc\n");
+        assert_eq!(m.rotate_ccw().render_ascii(), "cf\nbe\nad\n");
+        assert_eq!(m.flip_h().render_ascii(), "cba\nfed\n");
+        assert_eq!(m.flip_v().render_ascii(), "def\nabc\n");
+        assert_eq!(m.rotate_cw().rotate_ccw(), m);
+        assert_eq!(m.rotate_cw().rotate_cw(), m.flip_h().flip_v());
+    }
+
+    #[test]
+    fn find_and_count() {
+        let m = Matrix::<char>::parse_ascii("..#\n#.#\n").unwrap();
+        assert_eq!(m.find(|c| *c == '#'), Some(point(2, 0)));
+        assert_eq!(m.find(|c| *c == 'x'), None);
+        assert_eq!(m.count(|c| *c == '#'), 3);
+    }
+
+    #[test]
+    fn get_and_neighbors() {
+        let mut m = Matrix::from_fn(3, 2, |p| p.y * 10 + p.x);
+        assert_eq!(m.get(point(2, 1)), Some(&12));
+        assert_eq!(m.get(point(3, 0)), None);
+        assert_eq!(m.get(point(0, 2)), None);
+        *m.get_mut(point(1, 1)).unwrap() = 99;
+        assert_eq!(m[point(1, 1)], 99);
+        assert_eq!(m.get_mut(point(3, 1)), None);
+
+        assert_eq!(m.neighbors4(point(0, 0)), vec![point(1, 0), point(0, 1)]);
+        assert_eq!(
+            m.neighbors4(point(1, 1)),
+            vec![point(1, 0), point(0, 1), point(2, 1)]
+        );
+        assert_eq!(
+            m.neighbors8(point(2, 0)),
+            vec![point(1, 0), point(1, 1), point(2, 1)]
+        );
+        assert_eq!(m.neighbors8(point(1, 0)).len(), 5);
+        assert_eq!(m.neighbor8_values(point(0, 1)), vec![0, 1, 99]);
+    }
+
+    #[test]
+    #[should_panic(expected = "point(3, 0) outside 3x2 matrix")]
+    fn index_out_of_bounds() {
+        // Would otherwise wrap around to the next row.
+        let m = Matrix::new(3, 2, 0);
+        let _ = m[point(3, 0)];
+    }
+
+    #[test]
+    #[should_panic(expected = "point(0, 2) outside 3x2 matrix")]
+    fn index_mut_out_of_bounds() {
+        let mut m = Matrix::new(3, 2, 0);
+        m[point(0, 2)] = 1;
+    }
 }
@@ -15,7 +15,7 @@
 //! Simple 2D integer-indexed point.
 use std::fmt;
 
-#[derive(Copy, Clone, PartialEq, Eq, Ord, PartialOrd)]
+#[derive(Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
 pub struct Point {
     pub y: usize,
     pub x: usize,
@@ -49,6 +49,30 @@ impl Point {
         point(self.x, self.y.checked_sub(1).unwrap())
     }
 
+    /// Step down, or None if that would overflow.
+    pub fn checked_down(&self) -> Option<Point> {
+        Some(point(self.x, self.y.checked_add(1)?))
+    }
+
+    /// Step left, or None if that would go below zero.
+    pub fn checked_left(&self) -> Option<Point> {
+        Some(point(self.x.checked_sub(1)?, self.y))
+    }
+
+    /// Step right, or None if that would overflow.
+    pub fn checked_right(&self) -> Option<Point> {
+        Some(point(self.x.checked_add(1)?, self.y))
+    }
+
+    /// Step up, or None if that would go below zero.
+    pub fn checked_up(&self) -> Option<Point> {
+        Some(point(self.x, self.y.checked_sub(1)?))
+    }
+
+    /// The orthogonal neighbors that don't go below zero.
+    ///
+    /// There's no upper bound, so these may be off the edge of a map:
+    /// `Matrix::neighbors4` gives only those within it.
     pub fn neighbors(&self) -> Vec<Point> {
         let mut r = Vec::with_capacity(4);
         if self.x > 0 {
@@ -62,3 +86,68 @@ impl Point {
         r
     }
 }
+
+/// A point that can be anywhere, including at negative coordinates.
+///
+/// Like `Point`, these sort in reading order: top to bottom, then left to
+/// right.
+#[derive(Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct IPoint {
+    pub y: isize,
+    pub x: isize,
+}
+
+impl fmt::Debug for IPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ipoint({}, {})", self.x, self.y)
+    }
+}
+
+pub fn ipoint(x: isize, y: isize) -> IPoint {
+    IPoint { x, y }
+}
+
+impl IPoint {
+    pub fn down(&self) -> IPoint {
+        ipoint(self.x, self.y + 1)
+    }
+
+    pub fn left(&self) -> IPoint {
+        ipoint(self.x - 1, self.y)
+    }
+
+    pub fn right(&self) -> IPoint {
+        ipoint(self.x + 1, self.y)
+    }
+
+    pub fn up(&self) -> IPoint {
+        ipoint(self.x, self.y - 1)
+    }
+
+    /// The four orthogonal neighbors, in reading order.
+    pub fn neighbors(&self) -> [IPoint; 4] {
+        [self.up(), self.left(), self.right(), self.down()]
+    }
+}
+
+impl From<Point> for IPoint {
+    fn from(p: Point) -> IPoint {
+        ipoint(p.x as isize, p.y as isize)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checked_steps() {
+        let p = point(0, 3);
+        assert_eq!(p.checked_left(), None);
+        assert_eq!(p.checked_up(), Some(point(0, 2)));
+        assert_eq!(p.checked_right(), Some(point(1, 3)));
+        assert_eq!(p.checked_down(), Some(point(0, 4)));
+        assert_eq!(point(4, 0).checked_up(), None);
+        assert_eq!(point(usize::MAX, 0).checked_right(), None);
+    }
+}
@@ -12,9 +12,155 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-//! Find the shortest path in a graph, using Djikstra's method.
+//! Find the shortest path in a graph, using Djikstra's method, or A* when
+//! there's a heuristic to guide it.
 
-use std::collections::{BTreeMap, BinaryHeap};
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::fmt;
+use std::hash::Hash;
+
+use crate::{Matrix, Point};
+
+type D = isize;
+
+/// A shortest route between two positions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route<P> {
+    /// Every position along the way, starting with the origin and ending
+    /// with the destination.
+    pub states: Vec<P>,
+    /// The cost of each step: `costs[i]` is the distance from `states[i]`
+    /// to `states[i + 1]`.
+    pub costs: Vec<D>,
+    /// The total distance.
+    pub distance: D,
+    /// The number of positions whose neighbors were explored in finding
+    /// this.
+    pub expanded: usize,
+}
+
+/// Bounds on how much work a search may do.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Don't follow paths longer than this.
+    pub max_distance: Option<D>,
+    /// Give up after exploring the neighbors of this many positions.
+    pub max_expansions: Option<usize>,
+}
+
+/// Why a search didn't find the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchError {
+    /// Everything reachable was explored.
+    Unreachable { expanded: usize },
+    /// Everything within the maximum distance was explored, but there were
+    /// longer paths not taken.
+    TooFar { expanded: usize },
+    /// The expansion budget ran out.
+    OutOfBudget { expanded: usize },
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SearchError::Unreachable { expanded } => write!(
+                f,
+                "destination is unreachable (expanded {} positions)",
+                expanded
+            ),
+            SearchError::TooFar { expanded } => write!(
+                f,
+                "destination is beyond the maximum distance (expanded {} positions)",
+                expanded
+            ),
+            SearchError::OutOfBudget { expanded } => {
+                write!(f, "gave up after expanding {} positions", expanded)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+/// Where a search keeps the shortest known distance to each position, and
+/// the position before it on that path.
+///
+/// A `BTreeMap` works for any ordered positions, and a `HashMap` for any
+/// hashable ones; neither is reliably faster than the other. If positions
+/// can be numbered compactly, a `DenseTable` avoids hashing and comparing
+/// altogether and is much faster, and a `Matrix` of `Option`s suits
+/// searches over a fixed grid.
+pub trait DistanceTable<P> {
+    /// The distance to `p` and the position before it, if `p` has been
+    /// reached.
+    fn get(&self, p: &P) -> Option<(D, P)>;
+
+    fn insert(&mut self, p: P, d: D, prev: P);
+}
+
+impl<P: Ord + Copy> DistanceTable<P> for BTreeMap<P, (D, P)> {
+    fn get(&self, p: &P) -> Option<(D, P)> {
+        BTreeMap::get(self, p).cloned()
+    }
+
+    fn insert(&mut self, p: P, d: D, prev: P) {
+        BTreeMap::insert(self, p, (d, prev));
+    }
+}
+
+impl<P: Hash + Eq + Copy> DistanceTable<P> for HashMap<P, (D, P)> {
+    fn get(&self, p: &P) -> Option<(D, P)> {
+        HashMap::get(self, p).cloned()
+    }
+
+    fn insert(&mut self, p: P, d: D, prev: P) {
+        HashMap::insert(self, p, (d, prev));
+    }
+}
+
+/// Points outside the matrix are never reached: inserting one panics.
+impl DistanceTable<Point> for Matrix<Option<(D, Point)>> {
+    fn get(&self, p: &Point) -> Option<(D, Point)> {
+        Matrix::get(self, *p).cloned().flatten()
+    }
+
+    fn insert(&mut self, p: Point, d: D, prev: Point) {
+        self[p] = Some((d, prev));
+    }
+}
+
+/// A table stored in a vector, indexed by a function that numbers the
+/// positions.
+///
+/// The vector grows to fit the largest index inserted, so the numbering
+/// should be compact, and it must give each position a different number.
+pub struct DenseTable<P, F> {
+    index: F,
+    d: Vec<Option<(D, P)>>,
+}
+
+impl<P: Copy, F: Fn(&P) -> usize> DenseTable<P, F> {
+    pub fn new(index: F) -> DenseTable<P, F> {
+        DenseTable {
+            index,
+            d: Vec::new(),
+        }
+    }
+}
+
+impl<P: Copy, F: Fn(&P) -> usize> DistanceTable<P> for DenseTable<P, F> {
+    fn get(&self, p: &P) -> Option<(D, P)> {
+        self.d.get((self.index)(p)).cloned().flatten()
+    }
+
+    fn insert(&mut self, p: P, d: D, prev: P) {
+        let i = (self.index)(&p);
+        if i >= self.d.len() {
+            self.d.resize((i + 1).max(self.d.len() * 2), None);
+        }
+        self.d[i] = Some((d, prev));
+    }
+}
 
 /// Find the shortest path in a graph, using Djikstra's method.
 ///
@@ -25,36 +171,459 @@ use std::collections::{BTreeMap, BinaryHeap};
 /// This takes a callback which returns all the neighbors from `p: P` and
 /// the distance to them, as tuples. The neighbor callback is mut to allow
 /// for internal caching.
-type D = isize;
-pub fn shortest_distance<P, N>(origin: P, dest: P, nbr_fn: &mut N) -> D
+///
+/// Returns None if `dest` can't be reached.
+pub fn shortest_distance<P, N>(origin: P, dest: P, nbr_fn: &mut N) -> Option<D>
 where
     P: Eq + Ord + Copy,
     N: FnMut(P) -> Vec<(P, D)>,
 {
-    // Next points to visit, indexed by the *negative* distance, so that the
-    // greatest value is the shortest.
-    let mut queue = BinaryHeap::<(D, P)>::new();
-    // Shortest known distance to reach any point.
-    let mut best = BTreeMap::<P, D>::new();
-    queue.push((0, origin));
-    loop {
-        let (d, p) = queue
-            .pop()
-            .expect("heap is empty without reaching destination");
-        let d = -d;
+    shortest_path(origin, dest, nbr_fn).map(|r| r.distance)
+}
+
+/// Find the shortest route from `origin` to `dest`, like
+/// `shortest_distance`, but also return the positions along the way.
+pub fn shortest_path<P, N>(origin: P, dest: P, nbr_fn: &mut N) -> Option<Route<P>>
+where
+    P: Eq + Ord + Copy,
+    N: FnMut(P) -> Vec<(P, D)>,
+{
+    bounded_shortest_path(origin, dest, nbr_fn, Limits::default()).ok()
+}
+
+/// Find the shortest route from `origin` to `dest`, giving up if it's
+/// longer than `limits.max_distance` or takes more than
+/// `limits.max_expansions` to find.
+///
+/// Useful for graphs that are infinite, or where the destination may not
+/// be reachable.
+pub fn bounded_shortest_path<P, N>(
+    origin: P,
+    dest: P,
+    nbr_fn: &mut N,
+    limits: Limits,
+) -> Result<Route<P>, SearchError>
+where
+    P: Eq + Ord + Copy,
+    N: FnMut(P) -> Vec<(P, D)>,
+{
+    astar_path(origin, dest, nbr_fn, |_| 0, limits)
+}
+
+/// Find the shortest route using the A* algorithm, which explores first
+/// the positions that look closest to `dest` according to `heuristic`.
+///
+/// The heuristic must never overestimate the remaining distance from a
+/// position to `dest`, or the route found may not be the shortest. The
+/// closer it is to the real distance, the fewer positions are expanded; a
+/// heuristic that always returns 0 makes this the same as Djikstra's method.
+pub fn astar_path<P, N, H>(
+    origin: P,
+    dest: P,
+    nbr_fn: &mut N,
+    heuristic: H,
+    limits: Limits,
+) -> Result<Route<P>, SearchError>
+where
+    P: Eq + Ord + Copy,
+    N: FnMut(P) -> Vec<(P, D)>,
+    H: FnMut(P) -> D,
+{
+    astar_path_with(
+        origin,
+        dest,
+        nbr_fn,
+        heuristic,
+        limits,
+        &mut BTreeMap::new(),
+    )
+}
+
+/// Find the shortest route using A*, like `astar_path`, keeping distances
+/// in `best`, which should start empty.
+pub fn astar_path_with<P, N, H, T>(
+    origin: P,
+    dest: P,
+    nbr_fn: &mut N,
+    mut heuristic: H,
+    limits: Limits,
+    best: &mut T,
+) -> Result<Route<P>, SearchError>
+where
+    P: Eq + Ord + Copy,
+    N: FnMut(P) -> Vec<(P, D)>,
+    H: FnMut(P) -> D,
+    T: DistanceTable<P>,
+{
+    // Next points to visit, indexed by the *negative* estimated total
+    // distance, so that the greatest value is the most promising. Among
+    // equally promising points, those furthest along are tried first.
+    let mut queue = BinaryHeap::<(D, D, P)>::new();
+    best.insert(origin, 0, origin);
+    queue.push((-heuristic(origin), 0, origin));
+    let mut expanded = 0;
+    // True if some path was cut off by the distance limit.
+    let mut pruned = false;
+    while let Some((_, d, p)) = queue.pop() {
+        if best.get(&p).is_some_and(|(best_d, _)| d > best_d) {
+            // A shorter path to p was found after this was queued, and
+            // has already been expanded.
+            continue;
+        }
         if p == dest {
             // Found a shortest path to the end
-            return d;
+            return Ok(route(best, origin, dest, expanded));
         }
+        if limits.max_expansions == Some(expanded) {
+            return Err(SearchError::OutOfBudget { expanded });
+        }
+        expanded += 1;
         for (np, step) in nbr_fn(p) {
             let nd = step + d;
-            if let Some(prev_d) = best.get(&np) {
-                if nd >= *prev_d {
+            if let Some((prev_d, _)) = best.get(&np) {
+                if nd >= prev_d {
                     continue; // Already found a shorter path; don't revisit.
                 }
             }
-            best.insert(np, nd);
-            queue.push((-nd, np));
+            let estimate = nd + heuristic(np);
+            if limits.max_distance.is_some_and(|max| estimate > max) {
+                pruned = true;
+                continue;
+            }
+            best.insert(np, nd, p);
+            queue.push((-estimate, nd, np));
+        }
+    }
+    if pruned {
+        Err(SearchError::TooFar { expanded })
+    } else {
+        Err(SearchError::Unreachable { expanded })
+    }
+}
+
+/// Find the shortest distance from `origin` to every reachable position.
+///
+/// When every step costs 1 this is a breadth-first flood fill.
+pub fn all_distances<P, N>(origin: P, nbr_fn: &mut N) -> BTreeMap<P, D>
+where
+    P: Eq + Ord + Copy,
+    N: FnMut(P) -> Vec<(P, D)>,
+{
+    let mut queue = BinaryHeap::<(D, P)>::new();
+    let mut best = BTreeMap::<P, D>::new();
+    best.insert(origin, 0);
+    queue.push((0, origin));
+    while let Some((d, p)) = queue.pop() {
+        let d = -d;
+        if d > best[&p] {
+            continue; // Already expanded at a shorter distance.
+        }
+        for (np, step) in nbr_fn(p) {
+            let nd = step + d;
+            if best.get(&np).is_none_or(|prev_d| nd < *prev_d) {
+                best.insert(np, nd);
+                queue.push((-nd, np));
+            }
+        }
+    }
+    best
+}
+
+/// Find the shortest route from `origin` to the nearest position for which
+/// `is_goal` is true, or None if none can be reached.
+///
+/// If several goals are equally near, the smallest is chosen: for `Point`
+/// that's the first in reading order. Goals tied through zero-cost steps
+/// count too.
+pub fn nearest<P, N, G>(origin: P, nbr_fn: &mut N, mut is_goal: G) -> Option<Route<P>>
+where
+    P: Eq + Ord + Copy,
+    N: FnMut(P) -> Vec<(P, D)>,
+    G: FnMut(P) -> bool,
+{
+    let mut queue = BinaryHeap::<(D, P)>::new();
+    let mut best = BTreeMap::<P, (D, P)>::new();
+    best.insert(origin, (0, origin));
+    queue.push((0, origin));
+    let mut expanded = 0;
+    // The smallest goal found so far, and its distance.
+    let mut found: Option<(P, D)> = None;
+    while let Some((d, p)) = queue.pop() {
+        let d = -d;
+        if let Some((goal, goal_d)) = found {
+            if d > goal_d {
+                // Every goal at that distance has been seen.
+                return Some(route(&best, origin, goal, expanded));
+            }
+        }
+        if d > best[&p].0 {
+            continue; // Already expanded at a shorter distance.
+        }
+        if is_goal(p) && found.is_none_or(|(goal, _)| p < goal) {
+            found = Some((p, d));
+        }
+        // Once a goal is found, only zero-cost steps can lead to another
+        // goal as near, so those are still followed, even from goals.
+        let limit = found.map(|(_, goal_d)| goal_d);
+        expanded += 1;
+        for (np, step) in nbr_fn(p) {
+            let nd = step + d;
+            if limit.is_some_and(|l| nd > l) {
+                continue;
+            }
+            if best.get(&np).is_none_or(|(prev_d, _)| nd < *prev_d) {
+                best.insert(np, (nd, p));
+                queue.push((-nd, np));
+            }
         }
     }
+    found.map(|(goal, _)| route(&best, origin, goal, expanded))
+}
+
+/// Find the shortest route from `origin` to whichever of `targets` is
+/// nearest, like `nearest`.
+pub fn nearest_of<P, N>(origin: P, targets: &[P], nbr_fn: &mut N) -> Option<Route<P>>
+where
+    P: Eq + Ord + Copy,
+    N: FnMut(P) -> Vec<(P, D)>,
+{
+    nearest(origin, nbr_fn, |p| targets.contains(&p))
+}
+
+/// Walk back from `dest` to `origin` through the predecessors.
+fn route<P: Eq + Copy, T: DistanceTable<P>>(
+    best: &T,
+    origin: P,
+    dest: P,
+    expanded: usize,
+) -> Route<P> {
+    let dist = |p| best.get(&p).unwrap().0;
+    let mut states = vec![dest];
+    let mut costs = Vec::new();
+    let mut p = dest;
+    while p != origin {
+        let (d, prev) = best.get(&p).unwrap();
+        costs.push(d - dist(prev));
+        states.push(prev);
+        p = prev;
+    }
+    states.reverse();
+    costs.reverse();
+    Route {
+        states,
+        costs,
+        distance: dist(dest),
+        expanded,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn route_on_a_line() {
+        // Positions 0..10 on a line, where stepping forward costs 3 but
+        // jumping two forward costs only 5.
+        let mut nbrs = |p: u32| {
+            let mut v = Vec::new();
+            if p < 10 {
+                v.push((p + 1, 3));
+            }
+            if p < 9 {
+                v.push((p + 2, 5));
+            }
+            v
+        };
+        let r = shortest_path(0, 8, &mut nbrs).unwrap();
+        assert_eq!(r.distance, 20);
+        assert_eq!(r.states, vec![0, 2, 4, 6, 8]);
+        assert_eq!(r.costs, vec![5; 4]);
+        assert_eq!(shortest_distance(0, 8, &mut nbrs), Some(20));
+        let r = shortest_path(9, 10, &mut nbrs).unwrap();
+        assert_eq!((r.states, r.costs, r.distance), (vec![9, 10], vec![3], 3));
+        let r = shortest_path(3, 3, &mut nbrs).unwrap();
+        assert_eq!((r.states, r.costs, r.distance), (vec![3], vec![], 0));
+    }
+
+    #[test]
+    fn unreachable_and_limits() {
+        // Positions 0..10 in a ring, plus 100 which can't be reached.
+        let mut nbrs = |p: u32| vec![((p + 1) % 10, 1), ((p + 9) % 10, 1)];
+        assert_eq!(shortest_distance(0, 100, &mut nbrs), None);
+        let unbounded = Limits::default();
+        assert_eq!(
+            bounded_shortest_path(0, 100, &mut nbrs, unbounded),
+            Err(SearchError::Unreachable { expanded: 10 })
+        );
+
+        let near = Limits {
+            max_distance: Some(3),
+            ..Limits::default()
+        };
+        let r = bounded_shortest_path(0, 7, &mut nbrs, near).unwrap();
+        assert_eq!(r.distance, 3);
+        let err = bounded_shortest_path(0, 5, &mut nbrs, near).unwrap_err();
+        assert_eq!(err, SearchError::TooFar { expanded: 7 });
+        assert_eq!(
+            err.to_string(),
+            "destination is beyond the maximum distance (expanded 7 positions)"
+        );
+
+        let budget = Limits {
+            max_expansions: Some(4),
+            ..Limits::default()
+        };
+        assert_eq!(
+            bounded_shortest_path(0, 5, &mut nbrs, budget),
+            Err(SearchError::OutOfBudget { expanded: 4 })
+        );
+        let r = bounded_shortest_path(0, 2, &mut nbrs, budget).unwrap();
+        assert_eq!((r.distance, r.expanded), (2, 4));
+    }
+
+    #[test]
+    fn astar_on_a_grid() {
+        // An open 50x50 grid, searched from one corner to the other.
+        let mut nbrs = |(x, y): (isize, isize)| {
+            [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+                .iter()
+                .filter(|(x, y)| (0..50).contains(x) && (0..50).contains(y))
+                .map(|p| (*p, 1))
+                .collect()
+        };
+        let manhattan = |(x, y): (isize, isize)| (49 - x) + (49 - y);
+        let unbounded = Limits::default();
+        let dijkstra = bounded_shortest_path((0, 0), (49, 49), &mut nbrs, unbounded).unwrap();
+        let astar = astar_path((0, 0), (49, 49), &mut nbrs, manhattan, unbounded).unwrap();
+        assert_eq!(dijkstra.distance, 98);
+        assert_eq!(astar.distance, 98);
+        assert!(dijkstra.expanded > 2000);
+        assert!(astar.expanded < 200, "expanded {}", astar.expanded);
+    }
+
+    /// A (y, x) position, which sorts in reading order.
+    type Cell = (usize, usize);
+
+    /// A grid of open cells, with walls marked by `#`.
+    fn maze_nbrs(maze: &'static str) -> impl FnMut(Cell) -> Vec<(Cell, D)> {
+        let rows: Vec<&[u8]> = maze.lines().map(str::as_bytes).collect();
+        move |(y, x)| {
+            [(y - 1, x), (y, x - 1), (y, x + 1), (y + 1, x)]
+                .iter()
+                .filter(|(y, x)| rows[*y][*x] != b'#')
+                .map(|p| (*p, 1))
+                .collect()
+        }
+    }
+
+    const MAZE: &str = "\
+#######
+#.....#
+#.###.#
+#.#...#
+#######";
+
+    #[test]
+    fn distances_everywhere() {
+        let d = all_distances((1, 1), &mut maze_nbrs(MAZE));
+        assert_eq!(d.len(), 11);
+        assert_eq!(d[&(1, 1)], 0);
+        assert_eq!(d[&(3, 1)], 2);
+        assert_eq!(d[&(3, 3)], 8);
+        assert_eq!(d.values().max(), Some(&8));
+    }
+
+    #[test]
+    fn nearest_goal() {
+        // (3, 1) and (1, 3) are both 2 away: the first in reading order wins.
+        let mut nbrs = maze_nbrs(MAZE);
+        let r = nearest_of((1, 1), &[(3, 1), (3, 4), (1, 3)], &mut nbrs).unwrap();
+        assert_eq!(r.states, vec![(1, 1), (1, 2), (1, 3)]);
+        assert_eq!(r.distance, 2);
+        let r = nearest((1, 1), &mut nbrs, |(y, x)| y == 3 && x > 3).unwrap();
+        assert_eq!(r.states.last(), Some(&(3, 5)));
+        assert_eq!(r.distance, 6);
+        assert_eq!(nearest((1, 1), &mut nbrs, |p| p == (2, 2)), None);
+        // The origin can be a goal too.
+        assert_eq!(
+            nearest_of((1, 1), &[(1, 1)], &mut nbrs).unwrap().distance,
+            0
+        );
+    }
+
+    #[test]
+    fn nearest_through_zero_cost_step() {
+        // Goals 2 and 5 are both 1 away, but 2 is only reached through 1,
+        // by a step that costs nothing.
+        let mut nbrs = |p: u32| match p {
+            0 => vec![(5, 1), (1, 1)],
+            1 => vec![(2, 0), (3, 1)],
+            _ => vec![],
+        };
+        let r = nearest(0, &mut nbrs, |p| p == 2 || p == 5).unwrap();
+        assert_eq!(r.states, vec![0, 1, 2]);
+        assert_eq!(r.distance, 1);
+    }
+
+    #[test]
+    fn tables_agree() {
+        let w = 7;
+        let mut nbrs = maze_nbrs(MAZE);
+        let h = |_| 0;
+        let unbounded = Limits::default();
+        let (a, b) = ((1, 1), (3, 3));
+        let expected = astar_path(a, b, &mut nbrs, h, unbounded).unwrap();
+        assert_eq!(expected.distance, 8);
+        let mut hash = HashMap::new();
+        let mut dense = DenseTable::new(|&(y, x): &Cell| y * w + x);
+        assert_eq!(
+            astar_path_with(a, b, &mut nbrs, h, unbounded, &mut hash),
+            Ok(expected.clone())
+        );
+        assert_eq!(
+            astar_path_with(a, b, &mut nbrs, h, unbounded, &mut dense),
+            Ok(expected.clone())
+        );
+
+        // The same maze, as a matrix of points.
+        let mut point_nbrs = |p: Point| {
+            nbrs((p.y, p.x))
+                .into_iter()
+                .map(|((y, x), d)| (crate::point(x, y), d))
+                .collect::<Vec<_>>()
+        };
+        let mut m = Matrix::new(w, 5, None);
+        let r = astar_path_with(
+            crate::point(1, 1),
+            crate::point(3, 3),
+            &mut point_nbrs,
+            |_| 0,
+            unbounded,
+            &mut m,
+        )
+        .unwrap();
+        assert_eq!(r.costs, expected.costs);
+        assert_eq!(m[crate::point(3, 3)].map(|(d, _)| d), Some(8));
+    }
+
+    #[test]
+    fn stale_entries_skipped() {
+        // 0 -> 2 directly costs 10, but via 1 costs 2. The expensive entry
+        // for 2 is still queued, but shouldn't be expanded again.
+        let mut expansions = Vec::new();
+        let mut nbrs = |p: u32| {
+            expansions.push(p);
+            match p {
+                0 => vec![(2, 10), (1, 1)],
+                1 => vec![(2, 1)],
+                2 => vec![(3, 20)],
+                _ => vec![],
+            }
+        };
+        let r = shortest_path(0, 3, &mut nbrs).unwrap();
+        assert_eq!(r.distance, 22);
+        assert_eq!(r.expanded, 3);
+        assert_eq!(expansions, vec![0, 1, 2]);
+    }
 }
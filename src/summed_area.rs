@@ -0,0 +1,176 @@
+// Copyright 2018 Google LLC
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+// 
+//     https://www.apache.org/licenses/LICENSE-2.0
+// 
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Summed-area tables, for constant-time sums over rectangles of a matrix.
+//!
+//! ```
+//! use aoc2018::{point, Matrix};
+//!
+//! let m = Matrix::from_fn(4, 3, |p| p.x + p.y);
+//! let t = m.summed_area();
+//! assert_eq!(t.sum(point(1, 1), 2, 2), 2 + 3 + 3 + 4);
+//! ```
+
+use std::ops::{Add, Sub};
+
+use crate::{point, Matrix, Point};
+
+/// The sums of every rectangle anchored at the top-left of a matrix.
+#[derive(Clone, Debug)]
+pub struct SummedArea<T> {
+    /// `t[point(x, y)]` is the sum of everything above and left of `(x, y)`,
+    /// exclusive, so the first row and column are zero.
+    t: Matrix<T>,
+}
+
+impl<T> Matrix<T>
+where
+    T: Copy + Default + Add<Output = T> + Sub<Output = T>,
+{
+    /// Build a table of the sums of this matrix.
+    pub fn summed_area(&self) -> SummedArea<T> {
+        let mut t = Matrix::new(self.width() + 1, self.height() + 1, T::default());
+        for p in self.iter_points() {
+            let (x, y) = (p.x + 1, p.y + 1);
+            t[point(x, y)] =
+                self[p] + t[point(x - 1, y)] + t[point(x, y - 1)] - t[point(x - 1, y - 1)];
+        }
+        SummedArea { t }
+    }
+}
+
+impl<T> SummedArea<T>
+where
+    T: Copy + Default + Add<Output = T> + Sub<Output = T>,
+{
+    /// Width of the original matrix.
+    pub fn width(&self) -> usize {
+        self.t.width() - 1
+    }
+
+    /// Height of the original matrix.
+    pub fn height(&self) -> usize {
+        self.t.height() - 1
+    }
+
+    /// The sum of the `w` by `h` rectangle whose top-left corner is at
+    /// `origin`.
+    pub fn sum(&self, origin: Point, w: usize, h: usize) -> T {
+        let (x0, y0) = (origin.x, origin.y);
+        let (x1, y1) = (x0 + w, y0 + h);
+        assert!(
+            x1 <= self.width() && y1 <= self.height(),
+            "{}x{} rectangle at {:?} doesn't fit",
+            w,
+            h,
+            origin
+        );
+        self.t[point(x1, y1)] + self.t[point(x0, y0)]
+            - self.t[point(x0, y1)]
+            - self.t[point(x1, y0)]
+    }
+}
+
+impl<T> SummedArea<T>
+where
+    T: Copy + Default + Add<Output = T> + Sub<Output = T> + Ord,
+{
+    /// The top-left corner and sum of the `size` by `size` square with the
+    /// largest sum, or None if no square that size fits.
+    ///
+    /// Ties go to the first corner in reading order.
+    pub fn max_square(&self, size: usize) -> Option<(Point, T)> {
+        if size == 0 || size > self.width() || size > self.height() {
+            return None;
+        }
+        let mut best: Option<(Point, T)> = None;
+        for y in 0..=(self.height() - size) {
+            for x in 0..=(self.width() - size) {
+                let p = point(x, y);
+                let s = self.sum(p, size, size);
+                if best.is_none_or(|(_, b)| s > b) {
+                    best = Some((p, s));
+                }
+            }
+        }
+        best
+    }
+
+    /// The top-left corner, size and sum of the square of any size with the
+    /// largest sum.
+    ///
+    /// Ties go to the smallest square.
+    pub fn max_square_any(&self) -> Option<(Point, usize, T)> {
+        let mut best: Option<(Point, usize, T)> = None;
+        for size in 1..=self.width().min(self.height()) {
+            if let Some((p, s)) = self.max_square(size) {
+                if best.is_none_or(|(_, _, b)| s > b) {
+                    best = Some((p, size, s));
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Sum by brute force, to check against.
+    fn slow_sum(m: &Matrix<i32>, origin: Point, w: usize, h: usize) -> i32 {
+        m.view(origin, w, h).values().sum()
+    }
+
+    fn sample() -> Matrix<i32> {
+        Matrix::from_fn(7, 5, |p| ((p.x * 7 + p.y * 13) % 11) as i32 - 5)
+    }
+
+    #[test]
+    fn every_rectangle() {
+        let m = sample();
+        let t = m.summed_area();
+        assert_eq!((t.width(), t.height()), (7, 5));
+        for y0 in 0..=5 {
+            for x0 in 0..=7 {
+                for h in 0..=(5 - y0) {
+                    for w in 0..=(7 - x0) {
+                        let p = point(x0, y0);
+                        assert_eq!(t.sum(p, w, h), slow_sum(&m, p, w, h), "{:?} {}x{}", p, w, h);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn max_square() {
+        let mut m = Matrix::new(4, 4, -1);
+        m[point(2, 1)] = 5;
+        m[point(3, 1)] = 5;
+        m[point(2, 2)] = 5;
+        let t = m.summed_area();
+        assert_eq!(t.max_square(1), Some((point(2, 1), 5)));
+        assert_eq!(t.max_square(2), Some((point(2, 1), 14)));
+        assert_eq!(t.max_square(5), None);
+        assert_eq!(t.max_square(0), None);
+        assert_eq!(t.max_square_any(), Some((point(2, 1), 2, 14)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn sum_out_of_bounds() {
+        sample().summed_area().sum(point(6, 0), 2, 1);
+    }
+}